@@ -28,9 +28,16 @@ pub mod register_index {
 pub mod register_initial {
     pub const SP_USR: u32 = 0x0300_7F00;
     pub const PC: u32 = 0x0800_0000;
-    pub const CPSR: u32 = 0b0001_0011;
+    pub const CPSR: u32 = 0b0001_1111;
     pub const SP_IRQ: u32 = 0x0300_7FA0;
     pub const SP_UND: u32 = 0x0300_7FE0;
+    pub const SP_SVC: u32 = 0x0300_7FE0;
+    pub const MULTIBOOT_PC: u32 = 0x0200_00C0;
+}
+
+pub mod register_reset {
+    pub const PC: u32 = 0x0000_0000;
+    pub const CPSR: u32 = 0b1101_0011;
 }
 
 pub mod flag_masks {
@@ -38,6 +45,7 @@ pub mod flag_masks {
     pub const Z: u32 = 1 << 30;
     pub const C: u32 = 1 << 29;
     pub const V: u32 = 1 << 28;
+    pub const I: u32 = 1 << 7;
     pub const T: u32 = 1 << 5;
 }
 
@@ -60,3 +68,217 @@ pub mod condition_codes {
 }
 
 pub const CONDITION_MASK: u32 = 0xF << 28;
+
+pub mod cartridge_header {
+    pub const LENGTH: usize = 0xC0;
+    pub const TITLE: usize = 0xA0;
+    pub const GAME_CODE: usize = 0xAC;
+    pub const MAKER_CODE: usize = 0xB0;
+    pub const FIXED_VALUE: usize = 0xB2;
+    pub const UNIT_CODE: usize = 0xB3;
+    pub const SOFTWARE_VERSION: usize = 0xBC;
+    pub const COMPLEMENT_CHECK: usize = 0xBD;
+
+    pub const MAX_ROM_SIZE: usize = 0x0200_0000;
+    pub const ROM_MASK: usize = 0x01FF_FFFF;
+}
+
+pub mod multiboot_header {
+    pub const BOOT_MODE: usize = 0xC4;
+    pub const SLAVE_ID: usize = 0xC5;
+    pub const LENGTH: usize = 0xE0;
+
+    pub const BOOT_MODE_MULTIPLAY: u8 = 0x03;
+}
+
+pub mod memory_region {
+    pub const BIOS: usize = 0x0000_0000;
+    pub const EWRAM: usize = 0x0200_0000;
+    pub const IWRAM: usize = 0x0300_0000;
+    pub const IO: usize = 0x0400_0000;
+    pub const PALETTE: usize = 0x0500_0000;
+    pub const VRAM: usize = 0x0600_0000;
+    pub const OAM: usize = 0x0700_0000;
+    pub const ROM: usize = 0x0800_0000;
+    pub const ROM_END: usize = 0x0DFF_FFFF;
+    pub const EEPROM: usize = 0x0D00_0000;
+    pub const EEPROM_LARGE_ROM: usize = 0x0DFF_FF00;
+    pub const SRAM: usize = 0x0E00_0000;
+    pub const SRAM_END: usize = 0x0FFF_FFFF;
+}
+
+pub mod gpio_address {
+    pub const DATA: usize = 0x0800_00C4;
+    pub const DIRECTION: usize = 0x0800_00C6;
+    pub const CONTROL: usize = 0x0800_00C8;
+}
+
+pub mod backup_size {
+    pub const SRAM: usize = 0x8000;
+    pub const FLASH_64K: usize = 0x1_0000;
+    pub const FLASH_128K: usize = 0x2_0000;
+    pub const EEPROM_512: usize = 0x200;
+    pub const EEPROM_8K: usize = 0x2000;
+}
+
+pub mod mode_bits {
+    pub const MASK: u32 = 0b1_1111;
+    pub const FIQ: u32 = 0b1_0001;
+    pub const IRQ: u32 = 0b1_0010;
+    pub const SVC: u32 = 0b1_0011;
+    pub const ABT: u32 = 0b1_0111;
+    pub const UND: u32 = 0b1_1011;
+}
+
+pub mod exception_vector {
+    pub const SWI: u32 = 0x0000_0008;
+    pub const IRQ: u32 = 0x0000_0018;
+}
+
+pub mod io_address {
+    pub const DISPCNT: usize = 0x0400_0000;
+    pub const DISPSTAT: usize = 0x0400_0004;
+    pub const VCOUNT: usize = 0x0400_0006;
+    pub const BG0CNT: usize = 0x0400_0008;
+    pub const BG0HOFS: usize = 0x0400_0010;
+    pub const BG2PA: usize = 0x0400_0020;
+    pub const BG2X: usize = 0x0400_0028;
+    pub const BG2Y: usize = 0x0400_002C;
+    pub const BG3PA: usize = 0x0400_0030;
+    pub const BG3X: usize = 0x0400_0038;
+    pub const BG3Y: usize = 0x0400_003C;
+    pub const WIN0H: usize = 0x0400_0040;
+    pub const WIN1H: usize = 0x0400_0042;
+    pub const WIN0V: usize = 0x0400_0044;
+    pub const WIN1V: usize = 0x0400_0046;
+    pub const WININ: usize = 0x0400_0048;
+    pub const WINOUT: usize = 0x0400_004A;
+    pub const MOSAIC: usize = 0x0400_004C;
+    pub const BLDCNT: usize = 0x0400_0050;
+    pub const BLDALPHA: usize = 0x0400_0052;
+    pub const BLDY: usize = 0x0400_0054;
+    pub const SOUND1CNT_L: usize = 0x0400_0060;
+    pub const SOUND1CNT_H: usize = 0x0400_0062;
+    pub const SOUND1CNT_X: usize = 0x0400_0064;
+    pub const SOUND2CNT_L: usize = 0x0400_0068;
+    pub const SOUND2CNT_H: usize = 0x0400_006C;
+    pub const SOUND3CNT_L: usize = 0x0400_0070;
+    pub const SOUND3CNT_H: usize = 0x0400_0072;
+    pub const SOUND3CNT_X: usize = 0x0400_0074;
+    pub const SOUND4CNT_L: usize = 0x0400_0078;
+    pub const SOUND4CNT_H: usize = 0x0400_007C;
+    pub const SOUNDCNT_L: usize = 0x0400_0080;
+    pub const SOUNDCNT_H: usize = 0x0400_0082;
+    pub const SOUNDCNT_X: usize = 0x0400_0084;
+    pub const SOUNDBIAS: usize = 0x0400_0088;
+    pub const WAVE_RAM: usize = 0x0400_0090;
+    pub const FIFO_A: usize = 0x0400_00A0;
+    pub const FIFO_B: usize = 0x0400_00A4;
+    pub const DMA0SAD: usize = 0x0400_00B0;
+    pub const TM0CNT_L: usize = 0x0400_0100;
+    pub const KEYINPUT: usize = 0x0400_0130;
+    pub const IE: usize = 0x0400_0200;
+    pub const IF: usize = 0x0400_0202;
+    pub const IME: usize = 0x0400_0208;
+    pub const POSTFLG: usize = 0x0400_0300;
+    pub const HALTCNT: usize = 0x0400_0301;
+}
+
+pub mod interrupt_flag {
+    pub const VBLANK: u16 = 1 << 0;
+    pub const HBLANK: u16 = 1 << 1;
+    pub const VCOUNT: u16 = 1 << 2;
+    pub const TIMER0: u16 = 1 << 3;
+    pub const SERIAL: u16 = 1 << 7;
+    pub const DMA0: u16 = 1 << 8;
+    pub const KEYPAD: u16 = 1 << 12;
+    pub const GAMEPAK: u16 = 1 << 13;
+}
+
+pub mod dispcnt {
+    pub const MODE_MASK: u16 = 0b0000_0111;
+    pub const FRAME_SELECT: u16 = 1 << 4;
+    pub const HBLANK_FREE: u16 = 1 << 5;
+    pub const OBJ_1D: u16 = 1 << 6;
+    pub const FORCED_BLANK: u16 = 1 << 7;
+    pub const BG0: u16 = 1 << 8;
+    pub const OBJ: u16 = 1 << 12;
+    pub const WIN0: u16 = 1 << 13;
+    pub const WIN1: u16 = 1 << 14;
+    pub const OBJ_WIN: u16 = 1 << 15;
+}
+
+pub mod bgcnt {
+    pub const PRIORITY_MASK: u16 = 0b0000_0011;
+    pub const MOSAIC: u16 = 1 << 6;
+    pub const COLOR_256: u16 = 1 << 7;
+    pub const WRAPAROUND: u16 = 1 << 13;
+}
+
+pub mod dispstat {
+    pub const VBLANK: u8 = 1 << 0;
+    pub const HBLANK: u8 = 1 << 1;
+    pub const VCOUNT_MATCH: u8 = 1 << 2;
+    pub const VBLANK_IRQ: u8 = 1 << 3;
+    pub const HBLANK_IRQ: u8 = 1 << 4;
+    pub const VCOUNT_IRQ: u8 = 1 << 5;
+    pub const STATUS_MASK: u8 = 0b0000_0111;
+}
+
+pub mod soundcnt {
+    pub const PSG_VOLUME_MASK: u16 = 0b0000_0011;
+    pub const FIFO_A_FULL_VOLUME: u16 = 1 << 2;
+    pub const FIFO_A_RIGHT: u16 = 1 << 8;
+    pub const FIFO_A_LEFT: u16 = 1 << 9;
+    pub const FIFO_A_TIMER: u16 = 1 << 10;
+    pub const FIFO_A_RESET: u16 = 1 << 11;
+    pub const FIFO_B_RESET: u16 = 1 << 15;
+    pub const MASTER_ENABLE: u8 = 1 << 7;
+}
+
+pub mod dmacnt {
+    pub const DEST_SHIFT: u16 = 5;
+    pub const SOURCE_SHIFT: u16 = 7;
+    pub const REPEAT: u16 = 1 << 9;
+    pub const WORD: u16 = 1 << 10;
+    pub const GAME_PAK_DRQ: u16 = 1 << 11;
+    pub const TIMING_SHIFT: u16 = 12;
+    pub const IRQ: u16 = 1 << 14;
+    pub const ENABLE: u16 = 1 << 15;
+}
+
+pub mod tmcnt {
+    pub const PRESCALER_MASK: u8 = 0b0000_0011;
+    pub const CASCADE: u8 = 1 << 2;
+    pub const IRQ: u8 = 1 << 6;
+    pub const ENABLE: u8 = 1 << 7;
+    pub const READ_MASK: u8 = 0b1100_0111;
+}
+
+pub mod soundbias {
+    pub const LEVEL_MASK: u16 = 0x03FE;
+    pub const RESOLUTION_SHIFT: u16 = 14;
+}
+
+pub mod lcd {
+    pub const WIDTH: usize = 240;
+    pub const HEIGHT: usize = 160;
+    pub const HDRAW_CYCLES: u32 = 960;
+    pub const LINE_CYCLES: u32 = 1232;
+    pub const LINES: u16 = 228;
+}
+
+pub mod bios_address {
+    pub const IF: usize = 0x0300_7FF8;
+    pub const MULTIBOOT_FLAG: usize = 0x0300_7FFA;
+    pub const RESERVED_AREA: usize = 0x0300_7E00;
+}
+
+pub const BIOS_SIZE: usize = 0x4000;
+pub const PALETTE_SIZE: usize = 0x400;
+pub const VRAM_SIZE: usize = 0x1_8000;
+pub const OAM_SIZE: usize = 0x400;
+pub const OBJ_VRAM: usize = 0x0601_0000;
+pub const OBJ_PALETTE: usize = 0x0500_0200;
+pub const EWRAM_SIZE: usize = 0x4_0000;
+pub const IWRAM_SIZE: usize = 0x8000;
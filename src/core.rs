@@ -1,3 +1,16 @@
+pub mod apu;
+pub mod audio;
+pub mod bios;
 pub mod bus;
+pub mod cartridge;
 pub mod cpu;
 pub mod disassembler;
+pub mod dma;
+pub mod elf;
+pub mod gba;
+pub mod multiboot;
+pub mod ppu;
+pub mod screenshot;
+pub mod symbols;
+pub mod timer;
+pub mod video;
@@ -0,0 +1,389 @@
+use std::collections::VecDeque;
+
+use crate::core::apu::fifo::Fifo;
+use crate::core::apu::psg::Noise;
+use crate::core::apu::psg::Square;
+use crate::core::apu::psg::Wave;
+use crate::core::ppu::io16;
+use crate::constants::io_address;
+use crate::constants::soundbias;
+use crate::constants::soundcnt;
+
+pub mod fifo;
+pub mod psg;
+
+// the frame sequencer steps at 512Hz, clocking lengths, sweep and envelopes
+const SEQUENCER_CYCLES: u32 = 32768;
+// samples come out at 32768Hz. the hardware can sample up to 8 times as
+// often at lower resolutions, and those get averaged down to this
+pub const SAMPLE_CYCLES: u32 = 512;
+pub const SAMPLE_RATE: u32 = 32768;
+// a second of samples, beyond which the oldest are dropped if nothing is
+// taking them
+const MAX_SAMPLES: usize = SAMPLE_RATE as usize;
+
+// psg 1 to 4 then fifo a and b
+pub const CHANNELS: usize = 6;
+pub const CHANNEL_NAMES: [&str; CHANNELS] = ["psg1", "psg2", "psg3", "psg4", "fifo_a", "fifo_b"];
+// the channel levels are in quarter steps of the dac
+const LEVEL_FRACTION_BITS: u32 = 2;
+
+// the psg registers, everything before SOUNDCNT_H. they're cleared and
+// stop taking writes while the sound hardware is off
+const PSG_END: usize = io_address::SOUNDCNT_L + 1;
+const WAVE_RAM_END: usize = io_address::WAVE_RAM + 15;
+const FIFO_A_END: usize = io_address::FIFO_A + 3;
+const FIFO_B_END: usize = io_address::FIFO_B + 3;
+const SOUNDCNT_H_HIGH: usize = io_address::SOUNDCNT_H + 1;
+const SOUND1CNT_H_HIGH: usize = io_address::SOUND1CNT_H + 1;
+const SOUND1CNT_X_HIGH: usize = io_address::SOUND1CNT_X + 1;
+const SOUND2CNT_L_HIGH: usize = io_address::SOUND2CNT_L + 1;
+const SOUND2CNT_H_HIGH: usize = io_address::SOUND2CNT_H + 1;
+const SOUND3CNT_H_HIGH: usize = io_address::SOUND3CNT_H + 1;
+const SOUND3CNT_X_HIGH: usize = io_address::SOUND3CNT_X + 1;
+const SOUND4CNT_L_HIGH: usize = io_address::SOUND4CNT_L + 1;
+const SOUND4CNT_H_HIGH: usize = io_address::SOUND4CNT_H + 1;
+
+pub struct Apu {
+    pub square1: Square,
+    pub square2: Square,
+    pub wave: Wave,
+    pub noise: Noise,
+    // direct sound a and b
+    pub fifos: [Fifo; 2],
+    // fifos that have run low since dma last looked, a in bit 0
+    pub fifo_requests: u8,
+    sequencer_step: u8,
+    sequencer_timer: u32,
+    sample_timer: u32,
+    // hardware samples waiting to be averaged into the next output one
+    window: [i32; 2],
+    window_samples: i32,
+    window_cycles: u32,
+    // stereo pairs, left first
+    pub samples: VecDeque<[i16; 2]>,
+    pub record_channels: bool,
+    channel_samples: VecDeque<[[i16; 2]; CHANNELS]>,
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu {
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            fifos: [Fifo::new(), Fifo::new()],
+            fifo_requests: 0,
+            sequencer_step: 0,
+            sequencer_timer: SEQUENCER_CYCLES,
+            sample_timer: SAMPLE_CYCLES,
+            window: [0, 0],
+            window_samples: 0,
+            window_cycles: 0,
+            samples: VecDeque::new(),
+            record_channels: false,
+            channel_samples: VecDeque::new(),
+        }
+    }
+
+    // runs the channels on, taking a sample of the mix whenever one is due
+    pub fn tick(&mut self, cycles: u32, ram: &[u8]) {
+        let enabled = (ram[io_address::SOUNDCNT_X] & soundcnt::MASTER_ENABLE) != 0;
+
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let step = remaining.min(self.sequencer_timer).min(self.sample_timer);
+            remaining -= step;
+            self.sequencer_timer -= step;
+            self.sample_timer -= step;
+
+            if enabled {
+                self.run_channels(step);
+            }
+
+            if self.sequencer_timer == 0 {
+                self.sequencer_timer = SEQUENCER_CYCLES;
+                if enabled {
+                    self.clock_sequencer();
+                }
+            }
+
+            if self.sample_timer == 0 {
+                let interval = SAMPLE_CYCLES >> (io16(ram, io_address::SOUNDBIAS) >> soundbias::RESOLUTION_SHIFT);
+                self.sample_timer = interval;
+                let levels = if enabled { self.channel_levels(ram) } else { [[0; 2]; CHANNELS] };
+                self.sample(&levels, Apu::mix(&levels, ram), interval);
+            }
+        }
+    }
+
+    fn sample(&mut self, levels: &[[i32; 2]; CHANNELS], level: [i32; 2], interval: u32) {
+        self.window[0] += level[0];
+        self.window[1] += level[1];
+        self.window_samples += 1;
+        self.window_cycles += interval;
+
+        if self.window_cycles >= SAMPLE_CYCLES {
+            // the dac level is relative to the bias, so it only fits in an
+            // i16 without clipping when the bias is near the middle
+            let count = self.window_samples;
+            let sample = self.window.map(|sum| ((sum / count) << 6).clamp(i16::MIN as i32, i16::MAX as i32) as i16);
+            if self.samples.len() == MAX_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(sample);
+
+            // the channels on their own are only wanted for recording, and
+            // just take the latest hardware sample
+            if self.record_channels {
+                if self.channel_samples.len() == MAX_SAMPLES {
+                    self.channel_samples.pop_front();
+                }
+                self.channel_samples.push_back(levels.map(|level| level.map(|side| (side << (6 - LEVEL_FRACTION_BITS)) as i16)));
+            }
+
+            self.window = [0, 0];
+            self.window_samples = 0;
+            self.window_cycles = 0;
+        }
+    }
+
+    // timer 0 or 1 overflowed, so the fifos it drives move on a sample
+    pub fn timer_overflow(&mut self, timer: usize, ram: &[u8]) {
+        let control = io16(ram, io_address::SOUNDCNT_H);
+
+        for (i, fifo) in self.fifos.iter_mut().enumerate() {
+            let selected = ((control & (soundcnt::FIFO_A_TIMER << (4 * i))) != 0) as usize;
+            if selected == timer && fifo.pop() {
+                self.fifo_requests |= 1 << i;
+            }
+        }
+    }
+
+    // everything sampled since last time
+    pub fn take_samples(&mut self) -> Vec<[i16; 2]> {
+        self.samples.drain(..).collect()
+    }
+
+    // the same for each channel apart, while record_channels is set
+    pub fn take_channel_samples(&mut self) -> Vec<[[i16; 2]; CHANNELS]> {
+        self.channel_samples.drain(..).collect()
+    }
+
+    fn run_channels(&mut self, cycles: u32) {
+        if self.square1.enabled {
+            self.square1.run(cycles);
+        }
+        if self.square2.enabled {
+            self.square2.run(cycles);
+        }
+        if self.wave.enabled {
+            self.wave.run(cycles);
+        }
+        if self.noise.enabled {
+            self.noise.run(cycles);
+        }
+    }
+
+    // lengths on even steps, the sweep on 2 and 6 and envelopes on 7
+    fn clock_sequencer(&mut self) {
+        let step = self.sequencer_step;
+        self.sequencer_step = (step + 1) % 8;
+
+        if step.is_multiple_of(2) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if step == 2 || step == 6 {
+            self.square1.clock_sweep();
+        }
+        if step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+    }
+
+    // each channel's part of the output, left then right, in quarters of a
+    // step of the 10 bit dac so the psg ratio doesn't round each channel on
+    // its own. the psg channels are panned by SOUNDCNT_L and scaled by its
+    // master volumes and the psg ratio in SOUNDCNT_H, up to 120 steps either
+    // side of silence. direct sound is at half or full volume and panned by
+    // SOUNDCNT_H
+    fn channel_levels(&self, ram: &[u8]) -> [[i32; 2]; CHANNELS] {
+        let psg_control = io16(ram, io_address::SOUNDCNT_L);
+        let control = io16(ram, io_address::SOUNDCNT_H);
+        let ratio_shift = match control & soundcnt::PSG_VOLUME_MASK {
+            0 => 0,
+            1 => 1,
+            _ => 2,
+        };
+
+        let psg = [self.square1.output(), self.square2.output(), self.wave.output(), self.noise.output()];
+        let fifo = [0, 1].map(|i| {
+            let full = (control & (soundcnt::FIFO_A_FULL_VOLUME << i)) != 0;
+            (self.fifos[i].sample as i32 * if full { 4 } else { 2 }) << LEVEL_FRACTION_BITS
+        });
+
+        let mut levels = [[0; 2]; CHANNELS];
+        for (channel, level) in levels.iter_mut().enumerate() {
+            // the psg has left in the high bits and right in the low ones
+            for (side, (volume_shift, enable_shift)) in [(4, 12), (0, 8)].into_iter().enumerate() {
+                level[side] = if channel < 4 {
+                    let volume = ((psg_control >> volume_shift) & 0x7) as i32 + 1;
+                    let enabled = (psg_control & (1 << (enable_shift + channel))) != 0;
+                    if enabled { (psg[channel] as i32 * volume) << ratio_shift } else { 0 }
+                } else {
+                    let enable = [soundcnt::FIFO_A_LEFT, soundcnt::FIFO_A_RIGHT][side] << (4 * (channel - 4));
+                    if (control & enable) != 0 { fifo[channel - 4] } else { 0 }
+                };
+            }
+        }
+
+        levels
+    }
+
+    // what the dac puts out, relative to silence. everything is added onto
+    // the bias level from SOUNDBIAS, clipped to 10 bits and cut down to the
+    // resolution it selects, 9 bits by default and one less each step up
+    fn mix(levels: &[[i32; 2]; CHANNELS], ram: &[u8]) -> [i32; 2] {
+        let bias = io16(ram, io_address::SOUNDBIAS);
+        let level = (bias & soundbias::LEVEL_MASK) as i32;
+        let dropped = 1 + (bias >> soundbias::RESOLUTION_SHIFT);
+
+        [0, 1].map(|side| {
+            let sum = levels.iter().map(|channel| channel[side]).sum::<i32>() >> LEVEL_FRACTION_BITS;
+            let output = (level + sum).clamp(0, 0x3FF);
+            ((output >> dropped) << dropped) - level
+        })
+    }
+
+    // which channels are playing, for the low bits of SOUNDCNT_X
+    fn status(&self) -> u8 {
+        [self.square1.enabled, self.square2.enabled, self.wave.enabled, self.noise.enabled]
+            .iter()
+            .enumerate()
+            .fold(0, |status, (channel, &enabled)| status | ((enabled as u8) << channel))
+    }
+
+    // lengths and frequencies can't be read back, and triggers read as 0
+    pub fn read(&self, addr: usize, ram: &[u8]) -> u8 {
+        if (io_address::WAVE_RAM..=WAVE_RAM_END).contains(&addr) {
+            return self.wave.read_ram(addr - io_address::WAVE_RAM);
+        }
+
+        let mask: u16 = match addr & !1 {
+            io_address::SOUND1CNT_L => 0x007F,
+            io_address::SOUND1CNT_H | io_address::SOUND2CNT_L => 0xFFC0,
+            io_address::SOUND1CNT_X | io_address::SOUND2CNT_H | io_address::SOUND3CNT_X => 0x4000,
+            io_address::SOUND3CNT_L => 0x00E0,
+            io_address::SOUND3CNT_H => 0xE000,
+            io_address::SOUND4CNT_L => 0xFF00,
+            io_address::SOUND4CNT_H => 0x40FF,
+            io_address::SOUNDCNT_L => 0xFF77,
+            io_address::SOUNDCNT_H => 0x770F,
+            io_address::SOUNDCNT_X => 0x0080,
+            _ => 0,
+        };
+        let byte = ram[addr] & (mask >> ((addr & 1) * 8)) as u8;
+
+        if addr == io_address::SOUNDCNT_X { byte | self.status() } else { byte }
+    }
+
+    // stores the register and passes the change on to its channel
+    pub fn write(&mut self, addr: usize, data: u8, ram: &mut [u8]) {
+        match addr {
+            io_address::WAVE_RAM..=WAVE_RAM_END => return self.wave.write_ram(addr - io_address::WAVE_RAM, data),
+            io_address::FIFO_A..=FIFO_A_END => return self.fifos[0].push(data),
+            io_address::FIFO_B..=FIFO_B_END => return self.fifos[1].push(data),
+            _ => {},
+        }
+
+        let enabled = (ram[io_address::SOUNDCNT_X] & soundcnt::MASTER_ENABLE) != 0;
+        if !enabled && addr <= PSG_END {
+            return;
+        }
+        ram[addr] = data;
+
+        match addr {
+            io_address::SOUND1CNT_L => self.square1.write_sweep(data),
+            io_address::SOUND1CNT_H => self.square1.write_duty_length(data),
+            SOUND1CNT_H_HIGH => self.square1.write_envelope(data),
+            io_address::SOUND1CNT_X => self.square1.write_frequency_low(data),
+            SOUND1CNT_X_HIGH => self.square1.write_frequency_high(data),
+            io_address::SOUND2CNT_L => self.square2.write_duty_length(data),
+            SOUND2CNT_L_HIGH => self.square2.write_envelope(data),
+            io_address::SOUND2CNT_H => self.square2.write_frequency_low(data),
+            SOUND2CNT_H_HIGH => self.square2.write_frequency_high(data),
+            io_address::SOUND3CNT_L => self.wave.write_control(data),
+            io_address::SOUND3CNT_H => self.wave.write_length(data),
+            SOUND3CNT_H_HIGH => self.wave.write_volume(data),
+            io_address::SOUND3CNT_X => self.wave.write_frequency_low(data),
+            SOUND3CNT_X_HIGH => self.wave.write_frequency_high(data),
+            io_address::SOUND4CNT_L => self.noise.write_length(data),
+            SOUND4CNT_L_HIGH => self.noise.write_envelope(data),
+            io_address::SOUND4CNT_H => self.noise.write_frequency(data),
+            SOUND4CNT_H_HIGH => self.noise.write_control(data),
+            // the reset bits act once and aren't kept
+            SOUNDCNT_H_HIGH => {
+                for (i, fifo) in self.fifos.iter_mut().enumerate() {
+                    if (data & ((soundcnt::FIFO_A_RESET >> 8) << (4 * i)) as u8) != 0 {
+                        fifo.reset();
+                    }
+                }
+                ram[addr] &= !((soundcnt::FIFO_A_RESET | soundcnt::FIFO_B_RESET) >> 8) as u8;
+            },
+            io_address::SOUNDCNT_X if (data & soundcnt::MASTER_ENABLE) == 0 => self.power_off(ram),
+            _ => {},
+        }
+    }
+
+    // turning the sound hardware off silences the psg and clears its
+    // registers. wave ram is left alone
+    fn power_off(&mut self, ram: &mut [u8]) {
+        ram[io_address::SOUND1CNT_L..=PSG_END].fill(0);
+
+        self.square1 = Square::new(true);
+        self.square2 = Square::new(false);
+        self.wave.reset();
+        self.noise = Noise::new();
+        self.sequencer_step = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::bus::Memory;
+
+    // both fifos at full volume on both sides, which is beyond what the dac
+    // can put out either way from the bias
+    fn loudest(sample: i32) -> [[i32; 2]; CHANNELS] {
+        let level = (sample * 4) << LEVEL_FRACTION_BITS;
+        [[0, 0], [0, 0], [0, 0], [0, 0], [level, level], [level, level]]
+    }
+
+    fn output(bias: u16, levels: &[[i32; 2]; CHANNELS]) -> [i16; 2] {
+        let mut memory = Memory::new();
+        memory.whalf(io_address::SOUNDBIAS, bias);
+
+        let mut apu = Apu::new();
+        apu.sample(levels, Apu::mix(levels, memory.ram()), SAMPLE_CYCLES);
+        apu.take_samples()[0]
+    }
+
+    #[test]
+    fn no_bias_clips_instead_of_wrapping() {
+        assert_eq!(output(0, &loudest(127)), [i16::MAX; 2]);
+        assert_eq!(output(0, &loudest(-128)), [0; 2]);
+    }
+
+    #[test]
+    fn full_bias_clips_instead_of_wrapping() {
+        assert_eq!(output(0x3FE, &loudest(-128)), [i16::MIN; 2]);
+        assert_eq!(output(0x3FE, &loudest(127)), [0; 2]);
+    }
+}
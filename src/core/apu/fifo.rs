@@ -0,0 +1,44 @@
+use std::collections::VecDeque;
+
+// holds 8 words of signed 8 bit samples
+const CAPACITY: usize = 32;
+// a dma refill is asked for once it's down to half full
+const REFILL_LEVEL: usize = 16;
+
+// one of the two direct sound channels. the game keeps it fed, normally by
+// dma, and a timer overflow moves the next sample out to the dac
+pub struct Fifo {
+    buffer: VecDeque<i8>,
+    // what's playing, held until the next pop
+    pub sample: i8,
+}
+
+impl Fifo {
+    pub fn new() -> Fifo {
+        Fifo { buffer: VecDeque::with_capacity(CAPACITY), sample: 0 }
+    }
+
+    // writes past full are lost
+    pub fn push(&mut self, data: u8) {
+        if self.buffer.len() < CAPACITY {
+            self.buffer.push_back(data as i8);
+        }
+    }
+
+    // an empty fifo keeps playing the last sample. true when it wants more
+    pub fn pop(&mut self) -> bool {
+        if let Some(sample) = self.buffer.pop_front() {
+            self.sample = sample;
+        }
+        self.len() <= REFILL_LEVEL
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.sample = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+}
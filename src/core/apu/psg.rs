@@ -0,0 +1,480 @@
+// the four channels carried over from the game boy. they run off the same
+// 4.19MHz clock there, which is every 4 cycles here
+
+// the high byte of a square or noise channel's control register
+pub struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    timer: u8,
+    pub volume: u8,
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope { initial: 0, increase: false, period: 0, timer: 0, volume: 0 }
+    }
+
+    fn write(&mut self, data: u8) {
+        self.period = data & 0x7;
+        self.increase = (data & 0x8) != 0;
+        self.initial = data >> 4;
+    }
+
+    // silencing the starting volume and counting down turns the channel's
+    // dac off, which stops the channel outright
+    fn dac_enabled(&self) -> bool {
+        self.initial != 0 || self.increase
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+
+    // 64 times a second
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+// stops the channel after a set time when enabled. it counts up from the
+// written value to the maximum, 64 steps or 256 for the wave channel
+pub struct Length {
+    counter: u16,
+    max: u16,
+    pub enabled: bool,
+}
+
+impl Length {
+    fn new(max: u16) -> Length {
+        Length { counter: 0, max, enabled: false }
+    }
+
+    fn load(&mut self, value: u16) {
+        self.counter = self.max - value;
+    }
+
+    fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    // 256 times a second. false once the time is up
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter != 0;
+        }
+        true
+    }
+}
+
+// square 1's frequency sweep, from SOUND1CNT_L
+struct Sweep {
+    shift: u8,
+    decrease: bool,
+    period: u8,
+    timer: u8,
+    shadow: u16,
+    enabled: bool,
+}
+
+impl Sweep {
+    fn new() -> Sweep {
+        Sweep { shift: 0, decrease: false, period: 0, timer: 0, shadow: 0, enabled: false }
+    }
+
+    fn write(&mut self, data: u8) {
+        self.shift = data & 0x7;
+        self.decrease = (data & 0x8) != 0;
+        self.period = (data >> 4) & 0x7;
+    }
+
+    // a period of 0 counts as 8 for the timer
+    fn reload(&mut self) {
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn next_frequency(&self) -> u16 {
+        let change = self.shadow >> self.shift;
+        if self.decrease { self.shadow - change } else { self.shadow + change }
+    }
+}
+
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+pub struct Square {
+    pub enabled: bool,
+    duty: u8,
+    step: u8,
+    frequency: u16,
+    timer: u32,
+    pub length: Length,
+    pub envelope: Envelope,
+    // only square 1 has one
+    sweep: Option<Sweep>,
+}
+
+impl Square {
+    pub fn new(sweep: bool) -> Square {
+        Square {
+            enabled: false,
+            duty: 0,
+            step: 0,
+            frequency: 0,
+            timer: 0,
+            length: Length::new(64),
+            envelope: Envelope::new(),
+            sweep: if sweep { Some(Sweep::new()) } else { None },
+        }
+    }
+
+    pub fn write_sweep(&mut self, data: u8) {
+        if let Some(sweep) = &mut self.sweep {
+            sweep.write(data);
+        }
+    }
+
+    // duty in the top two bits, length in the rest
+    pub fn write_duty_length(&mut self, data: u8) {
+        self.duty = data >> 6;
+        self.length.load((data & 0x3F) as u16);
+    }
+
+    pub fn write_envelope(&mut self, data: u8) {
+        self.envelope.write(data);
+        if !self.envelope.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn write_frequency_low(&mut self, data: u8) {
+        self.frequency = (self.frequency & 0x700) | data as u16;
+    }
+
+    // the top three frequency bits, then length enable and the trigger
+    pub fn write_frequency_high(&mut self, data: u8) {
+        self.frequency = (self.frequency & 0xFF) | (((data & 0x7) as u16) << 8);
+        self.length.enabled = (data & 0x40) != 0;
+
+        if (data & 0x80) != 0 {
+            self.trigger();
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period();
+        self.length.trigger();
+        self.envelope.trigger();
+
+        let frequency = self.frequency;
+        let mut overflow = false;
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow = frequency;
+            sweep.reload();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+            overflow = sweep.shift != 0 && sweep.next_frequency() > 2047;
+        }
+        if overflow {
+            self.enabled = false;
+        }
+    }
+
+    // 16 cycles a duty step at the top frequency
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 16
+    }
+
+    pub fn run(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.step = (self.step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // 128 times a second. the new frequency is checked a second time after
+    // it's written back, and either going past the top stops the channel
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else { return };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer != 0 {
+            return;
+        }
+        sweep.reload();
+
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let frequency = sweep.next_frequency();
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if sweep.shift != 0 {
+            sweep.shadow = frequency;
+            self.frequency = frequency;
+            if sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    // -15 to 15, centred on silence
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let high = (DUTY_PATTERNS[self.duty as usize] >> self.step) & 1 != 0;
+        let volume = self.envelope.volume as i16;
+        if high { volume } else { -volume }
+    }
+}
+
+// plays back 4 bit samples from wave ram. the gba has two 32 sample banks
+// and can play one or both in turn. the cpu sees whichever bank isn't
+// selected for playing
+pub struct Wave {
+    pub enabled: bool,
+    dac_enabled: bool,
+    two_banks: bool,
+    bank: usize,
+    banks: [[u8; 16]; 2],
+    position: usize,
+    frequency: u16,
+    timer: u32,
+    // output shift, with 75% handled apart
+    volume: u8,
+    force_75: bool,
+    pub length: Length,
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        Wave {
+            enabled: false,
+            dac_enabled: false,
+            two_banks: false,
+            bank: 0,
+            banks: [[0; 16]; 2],
+            position: 0,
+            frequency: 0,
+            timer: 0,
+            volume: 0,
+            force_75: false,
+            length: Length::new(256),
+        }
+    }
+
+    // back to how it powers on, but keeping the samples
+    pub fn reset(&mut self) {
+        let banks = self.banks;
+        *self = Wave::new();
+        self.banks = banks;
+    }
+
+    // SOUND3CNT_L, the bank settings and the dac
+    pub fn write_control(&mut self, data: u8) {
+        self.two_banks = (data & 0x20) != 0;
+        self.bank = ((data >> 6) & 1) as usize;
+        self.dac_enabled = (data & 0x80) != 0;
+
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    pub fn write_length(&mut self, data: u8) {
+        self.length.load(data as u16);
+    }
+
+    pub fn write_volume(&mut self, data: u8) {
+        self.volume = (data >> 5) & 0x3;
+        self.force_75 = (data & 0x80) != 0;
+    }
+
+    pub fn write_frequency_low(&mut self, data: u8) {
+        self.frequency = (self.frequency & 0x700) | data as u16;
+    }
+
+    pub fn write_frequency_high(&mut self, data: u8) {
+        self.frequency = (self.frequency & 0xFF) | (((data & 0x7) as u16) << 8);
+        self.length.enabled = (data & 0x40) != 0;
+
+        if (data & 0x80) != 0 {
+            self.enabled = self.dac_enabled;
+            self.position = 0;
+            self.timer = self.period();
+            self.length.trigger();
+        }
+    }
+
+    pub fn read_ram(&self, offset: usize) -> u8 {
+        self.banks[self.bank ^ 1][offset]
+    }
+
+    pub fn write_ram(&mut self, offset: usize, data: u8) {
+        self.banks[self.bank ^ 1][offset] = data;
+    }
+
+    // 8 cycles a sample at the top frequency
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 8
+    }
+
+    pub fn run(&mut self, cycles: u32) {
+        let samples = if self.two_banks { 64 } else { 32 };
+
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % samples;
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // high nibble first. in two bank mode playback starts in the selected
+    // bank and carries on into the other
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let bank = (self.bank + self.position / 32) % 2;
+        let byte = self.banks[bank][(self.position % 32) / 2];
+        let sample = if self.position.is_multiple_of(2) { byte >> 4 } else { byte & 0xF };
+        let sample = sample as i16 * 2 - 15;
+
+        match (self.force_75, self.volume) {
+            (true, _) => sample * 3 / 4,
+            (_, 0) => 0,
+            (_, shift) => sample >> (shift - 1),
+        }
+    }
+}
+
+// pseudo random noise from a 15 bit shift register, or a 7 bit one for a
+// more tonal sound
+pub struct Noise {
+    pub enabled: bool,
+    divisor: u8,
+    narrow: bool,
+    shift: u8,
+    lfsr: u16,
+    timer: u32,
+    pub length: Length,
+    pub envelope: Envelope,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            enabled: false,
+            divisor: 0,
+            narrow: false,
+            shift: 0,
+            lfsr: 0x7FFF,
+            timer: 0,
+            length: Length::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    pub fn write_length(&mut self, data: u8) {
+        self.length.load((data & 0x3F) as u16);
+    }
+
+    pub fn write_envelope(&mut self, data: u8) {
+        self.envelope.write(data);
+        if !self.envelope.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    // divisor, register width and clock shift
+    pub fn write_frequency(&mut self, data: u8) {
+        self.divisor = data & 0x7;
+        self.narrow = (data & 0x8) != 0;
+        self.shift = data >> 4;
+    }
+
+    pub fn write_control(&mut self, data: u8) {
+        self.length.enabled = (data & 0x40) != 0;
+
+        if (data & 0x80) != 0 {
+            self.enabled = self.envelope.dac_enabled();
+            self.lfsr = 0x7FFF;
+            self.timer = self.period();
+            self.length.trigger();
+            self.envelope.trigger();
+        }
+    }
+
+    // a divisor of 0 counts as half of 1
+    fn period(&self) -> u32 {
+        let divisor = if self.divisor == 0 { 8 } else { self.divisor as u32 * 16 };
+        (divisor << self.shift) * 4
+    }
+
+    pub fn run(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+
+            let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            if self.narrow {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // the output is high while the low bit is clear
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let volume = self.envelope.volume as i16;
+        if (self.lfsr & 1) == 0 { volume } else { -volume }
+    }
+}
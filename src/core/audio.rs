@@ -0,0 +1,5 @@
+// getting the sound hardware's output to the host. like video, none of
+// this is console hardware, it takes the samples after the apu is done
+pub mod record;
+pub mod resample;
+pub mod stream;
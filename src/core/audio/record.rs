@@ -0,0 +1,74 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::core::apu::CHANNELS;
+use crate::core::apu::CHANNEL_NAMES;
+use crate::core::apu::SAMPLE_RATE;
+use crate::wav::WavWriter;
+
+// writes what the apu puts out to wav files at its own rate, untouched by
+// the resampler. the mix goes to the named file and each channel on its
+// own, when wanted, next to it with the channel's name on the end
+pub struct Recorder {
+    mix: WavWriter,
+    channels: Option<Vec<WavWriter>>,
+    // held from one frame until they're written
+    pending: Vec<[i16; 2]>,
+    pending_channels: Vec<[[i16; 2]; CHANNELS]>,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, separate_channels: bool) -> io::Result<Recorder> {
+        let mix = WavWriter::create(path, SAMPLE_RATE, 2)?;
+
+        let channels = if separate_channels {
+            let writers = CHANNEL_NAMES.iter()
+                .map(|name| WavWriter::create(&channel_path(path, name), SAMPLE_RATE, 2))
+                .collect::<io::Result<Vec<WavWriter>>>()?;
+            Some(writers)
+        } else {
+            None
+        };
+
+        Ok(Recorder { mix, channels, pending: Vec::new(), pending_channels: Vec::new() })
+    }
+
+    pub fn queue(&mut self, samples: &[[i16; 2]], channel_samples: &[[[i16; 2]; CHANNELS]]) {
+        self.pending.extend_from_slice(samples);
+        if self.channels.is_some() {
+            self.pending_channels.extend_from_slice(channel_samples);
+        }
+    }
+
+    pub fn write(&mut self) -> io::Result<()> {
+        self.mix.write(self.pending.as_flattened())?;
+        self.pending.clear();
+
+        if let Some(writers) = &mut self.channels {
+            for (channel, writer) in writers.iter_mut().enumerate() {
+                let samples: Vec<i16> = self.pending_channels.iter().flat_map(|sample| sample[channel]).collect();
+                writer.write(&samples)?;
+            }
+        }
+        self.pending_channels.clear();
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write()?;
+        self.mix.finish()?;
+
+        for writer in self.channels.into_iter().flatten() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+// music.wav becomes music_psg1.wav and so on
+pub fn channel_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{}_{}.wav", stem, name))
+}
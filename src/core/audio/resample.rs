@@ -0,0 +1,123 @@
+use std::f64::consts::PI;
+
+// the kernel reaches this many input samples either side of the point
+// being worked out
+const TAPS: usize = 16;
+// fractional positions the kernel is tabulated for
+const PHASES: usize = 256;
+// keeps the passband edge clear of the nyquist of the slower rate
+const ROLLOFF: f64 = 0.92;
+
+// converts between sample rates with a windowed sinc, which keeps what's
+// above the slower rate's nyquist limit from aliasing back down into the
+// audible range. the ratio can be nudged while running without restarting
+pub struct Resampler {
+    input_rate: f64,
+    output_rate: f64,
+    // multiplies the input consumed per output sample, from rate control
+    adjust: f64,
+    // the kernel for each phase, TAPS * 2 weights a row
+    table: Vec<f32>,
+    history: Vec<[f32; 2]>,
+    // where in history the next output falls, between two inputs
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Resampler {
+        let mut resampler = Resampler {
+            input_rate: input_rate as f64,
+            output_rate: output_rate as f64,
+            adjust: 1.0,
+            table: Vec::new(),
+            history: Vec::new(),
+            position: 0.0,
+        };
+        resampler.build_table();
+        resampler.reset();
+        resampler
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate as u32
+    }
+
+    pub fn set_output_rate(&mut self, output_rate: u32) {
+        self.output_rate = output_rate as f64;
+        self.build_table();
+        self.reset();
+    }
+
+    // above 1 makes less output from the same input, below 1 more
+    pub fn set_adjust(&mut self, adjust: f64) {
+        self.adjust = adjust;
+    }
+
+    // starts over from silence
+    pub fn reset(&mut self) {
+        self.history = vec![[0.0; 2]; TAPS * 2];
+        self.position = (TAPS - 1) as f64;
+    }
+
+    fn build_table(&mut self) {
+        let cutoff = (self.output_rate / self.input_rate).min(1.0) * ROLLOFF;
+        self.table = vec![0.0; PHASES * TAPS * 2];
+
+        for (phase, row) in self.table.chunks_mut(TAPS * 2).enumerate() {
+            let offset = phase as f64 / PHASES as f64;
+            let weights: Vec<f64> = (0..TAPS * 2)
+                .map(|tap| {
+                    let x = tap as f64 - (TAPS - 1) as f64 - offset;
+                    cutoff * sinc(cutoff * x) * blackman(x / TAPS as f64)
+                })
+                .collect();
+
+            // each row adds up to 1 so the level doesn't ripple with phase
+            let total: f64 = weights.iter().sum();
+            for (weight, value) in row.iter_mut().zip(weights) {
+                *weight = (value / total) as f32;
+            }
+        }
+    }
+
+    // resamples as much as the input allows, keeping what's left over for
+    // the next call
+    pub fn process(&mut self, input: &[[i16; 2]], output: &mut Vec<[f32; 2]>) {
+        self.history.extend(input.iter().map(|sample| sample.map(|channel| channel as f32 / 32768.0)));
+        let step = self.input_rate / self.output_rate * self.adjust;
+
+        loop {
+            let index = self.position as usize;
+            if index + TAPS >= self.history.len() {
+                break;
+            }
+
+            let phase = ((self.position - index as f64) * PHASES as f64) as usize;
+            let weights = &self.table[phase * TAPS * 2..(phase + 1) * TAPS * 2];
+            let window = &self.history[index + 1 - TAPS..=index + TAPS];
+
+            let mut sample = [0.0; 2];
+            for (weight, input) in weights.iter().zip(window) {
+                sample[0] += weight * input[0];
+                sample[1] += weight * input[1];
+            }
+            output.push(sample);
+
+            self.position += step;
+        }
+
+        // drop what no later output can reach
+        let consumed = (self.position as usize + 1).saturating_sub(TAPS);
+        self.history.drain(..consumed);
+        self.position -= consumed as f64;
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+// from -1 to 1
+fn blackman(t: f64) -> f64 {
+    0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos()
+}
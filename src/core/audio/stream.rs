@@ -0,0 +1,265 @@
+use crate::core::apu::SAMPLE_RATE;
+use crate::core::audio::resample::Resampler;
+
+pub const DEFAULT_RATE: u32 = 48000;
+// how much sound the buffer holds before the oldest is dropped
+const DEFAULT_LATENCY_MS: u32 = 200;
+
+// interleaved left and right samples with a fixed capacity. writing past
+// full overwrites the oldest, so a frontend that stalls loses a little
+// sound rather than falling further and further behind
+pub struct RingBuffer {
+    data: Box<[f32]>,
+    read: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    pub fn new(frames: usize) -> RingBuffer {
+        RingBuffer { data: vec![0.0; frames.max(1) * 2].into_boxed_slice(), read: 0, len: 0 }
+    }
+
+    // in stereo pairs
+    pub fn capacity(&self) -> usize {
+        self.data.len() / 2
+    }
+
+    pub fn len(&self) -> usize {
+        self.len / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.read = 0;
+        self.len = 0;
+    }
+
+    pub fn push(&mut self, frame: [f32; 2]) {
+        if self.len == self.data.len() {
+            self.read = (self.read + 2) % self.data.len();
+            self.len -= 2;
+        }
+
+        let write = (self.read + self.len) % self.data.len();
+        self.data[write] = frame[0];
+        self.data[write + 1] = frame[1];
+        self.len += 2;
+    }
+
+    // fills as much of `out` as there are samples for, returning how many
+    // values went in. an odd length leaves the last value alone so pairs
+    // are never split
+    fn read_with<T>(&mut self, out: &mut [T], convert: impl Fn(f32) -> T) -> usize {
+        let count = (out.len() & !1).min(self.len);
+
+        for value in out.iter_mut().take(count) {
+            *value = convert(self.data[self.read]);
+            self.read = (self.read + 1) % self.data.len();
+        }
+
+        self.len -= count;
+        count
+    }
+}
+
+// the sound ready for the host: resampled to its rate and waiting in a
+// ring buffer for the frontend's audio callback to take
+pub struct AudioStream {
+    resampler: Resampler,
+    buffer: RingBuffer,
+    latency_ms: u32,
+    // the most the rate may be bent either way to keep the buffer half
+    // full, when the stream is left to control it
+    dynamic_rate: Option<f64>,
+    scratch: Vec<[f32; 2]>,
+}
+
+impl AudioStream {
+    pub fn new(host_rate: u32) -> AudioStream {
+        AudioStream {
+            resampler: Resampler::new(SAMPLE_RATE, host_rate),
+            buffer: RingBuffer::new(frames_for(host_rate, DEFAULT_LATENCY_MS)),
+            latency_ms: DEFAULT_LATENCY_MS,
+            dynamic_rate: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    // how full the buffer is, from 0 to 1
+    pub fn fill_level(&self) -> f32 {
+        self.buffer.len() as f32 / self.buffer.capacity() as f32
+    }
+
+    // takes samples straight from the apu
+    pub fn push(&mut self, samples: &[[i16; 2]]) {
+        if let Some(deviation) = self.dynamic_rate {
+            let fill = self.fill_level() as f64;
+            self.resampler.set_adjust(1.0 + deviation * (2.0 * fill - 1.0));
+        }
+
+        self.scratch.clear();
+        self.resampler.process(samples, &mut self.scratch);
+        for &frame in &self.scratch {
+            self.buffer.push(frame);
+        }
+    }
+}
+
+// the reading and tuning side is for a frontend's audio callback, which
+// lives outside the core and has nothing here to call it yet
+#[allow(dead_code)]
+impl AudioStream {
+    pub fn host_rate(&self) -> u32 {
+        self.resampler.output_rate()
+    }
+
+    // starts the stream over at a new rate. a rate of 0 is refused and
+    // leaves the stream as it was
+    pub fn set_host_rate(&mut self, host_rate: u32) -> bool {
+        if host_rate == 0 {
+            return false;
+        }
+
+        self.resampler.set_output_rate(host_rate);
+        self.buffer = RingBuffer::new(frames_for(host_rate, self.latency_ms));
+        true
+    }
+
+    pub fn latency(&self) -> u32 {
+        self.latency_ms
+    }
+
+    pub fn set_latency(&mut self, latency_ms: u32) {
+        self.latency_ms = latency_ms;
+        self.buffer = RingBuffer::new(frames_for(self.host_rate(), latency_ms));
+    }
+
+    // a fixed nudge to the rate, for a frontend running its own control.
+    // 1.01 makes 1% fewer samples from the same emulated time
+    pub fn set_rate_adjust(&mut self, adjust: f64) {
+        self.dynamic_rate = None;
+        self.resampler.set_adjust(adjust);
+    }
+
+    // lets the stream steer the rate itself by up to `max_deviation`
+    // (0.005 is typical), speeding up as the buffer fills and slowing as it
+    // drains. the video can then be paced by the display and the sound
+    // follows along without gaps or pitch jumps
+    pub fn set_dynamic_rate(&mut self, max_deviation: Option<f64>) {
+        self.dynamic_rate = max_deviation;
+        if max_deviation.is_none() {
+            self.resampler.set_adjust(1.0);
+        }
+    }
+
+    // in stereo pairs
+    pub fn available(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    // interleaved left and right, returning how many values were filled.
+    // anything short of `out.len()` is an underrun and left for the caller
+    // to pad
+    pub fn read_i16(&mut self, out: &mut [i16]) -> usize {
+        self.buffer.read_with(out, |value| (value.clamp(-1.0, 1.0) * 32767.0) as i16)
+    }
+
+    pub fn read_f32(&mut self, out: &mut [f32]) -> usize {
+        self.buffer.read_with(out, |value| value)
+    }
+
+    // drops whatever is waiting, after a pause or a state change
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.resampler.reset();
+    }
+}
+
+fn frames_for(rate: u32, latency_ms: u32) -> usize {
+    (rate as u64 * latency_ms as u64 / 1000) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a second of a full scale 1 khz tone at half volume, left only
+    fn tone() -> Vec<[i16; 2]> {
+        (0..SAMPLE_RATE)
+            .map(|i| {
+                let phase = i as f64 * 1000.0 / SAMPLE_RATE as f64 * std::f64::consts::TAU;
+                [(phase.sin() * 16384.0) as i16, 0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resamples_to_the_host_rate() {
+        let mut stream = AudioStream::new(DEFAULT_RATE);
+        stream.set_latency(1000);
+        assert_eq!(stream.latency(), 1000);
+        assert_eq!(stream.capacity(), DEFAULT_RATE as usize);
+
+        stream.push(&tone());
+        let available = stream.available();
+        assert!(available.abs_diff(DEFAULT_RATE as usize) < 64, "{} frames", available);
+
+        // an odd length leaves the last value alone
+        let mut out = vec![0i16; 2001];
+        assert_eq!(stream.read_i16(&mut out), 2000);
+        assert_eq!(stream.available(), available - 1000);
+
+        let mut rest = vec![0.0f32; available * 2];
+        assert_eq!(stream.read_f32(&mut rest), (available - 1000) * 2);
+        assert_eq!(stream.available(), 0);
+
+        // past the kernel's start up the tone comes through at its level
+        let left = rest[200..].iter().step_by(2).fold(0.0f32, |peak, value| peak.max(value.abs()));
+        let right = rest[200..].iter().skip(1).step_by(2).fold(0.0f32, |peak, value| peak.max(value.abs()));
+        assert!((left - 0.5).abs() < 0.01, "left peaks at {}", left);
+        assert!(right < 0.001, "right peaks at {}", right);
+    }
+
+    #[test]
+    fn overflow_drops_the_oldest() {
+        let mut stream = AudioStream::new(DEFAULT_RATE);
+        stream.push(&tone());
+        assert_eq!(stream.available(), stream.capacity());
+        assert_eq!(stream.fill_level(), 1.0);
+
+        stream.clear();
+        assert!(stream.is_empty());
+        let mut out = [0i16; 8];
+        assert_eq!(stream.read_i16(&mut out), 0);
+    }
+
+    #[test]
+    fn rate_control_bends_the_output() {
+        let mut stream = AudioStream::new(DEFAULT_RATE);
+        stream.set_latency(2000);
+        stream.set_rate_adjust(1.01);
+        stream.push(&tone());
+        let adjusted = stream.available();
+        assert!(adjusted.abs_diff(DEFAULT_RATE as usize * 100 / 101) < 64, "{} frames", adjusted);
+
+        // an empty buffer makes the stream slow down and stretch the sound
+        assert!(!stream.set_host_rate(0));
+        assert_eq!(stream.host_rate(), DEFAULT_RATE);
+        assert!(stream.set_host_rate(32768));
+        assert_eq!(stream.host_rate(), 32768);
+        stream.set_dynamic_rate(Some(0.005));
+        stream.push(&tone());
+        assert!(stream.available() > 32768, "{} frames", stream.available());
+    }
+}
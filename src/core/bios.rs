@@ -0,0 +1,54 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::constants::BIOS_SIZE;
+
+pub mod hle;
+
+pub struct Bios {
+    pub image: Box<[u8]>,
+}
+
+pub enum BiosError {
+    Io(io::Error),
+    WrongSize(usize),
+}
+
+impl Bios {
+    // accepts the official dump or any open-source replacement, both of which
+    // fill the 16 KiB bios region exactly
+    pub fn load(path: &Path) -> Result<Bios, BiosError> {
+        let data = fs::read(path)?;
+
+        if data.len() != BIOS_SIZE {
+            return Err(BiosError::WrongSize(data.len()));
+        }
+
+        Ok(Bios { image: data.into_boxed_slice() })
+    }
+}
+
+impl From<io::Error> for BiosError {
+    fn from(err: io::Error) -> BiosError {
+        BiosError::Io(err)
+    }
+}
+
+impl fmt::Display for BiosError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BiosError::Io(err) => write!(f, "could not read bios: {}", err),
+            BiosError::WrongSize(size) => {
+                write!(f, "bios is {} bytes, expected exactly {}", size, BIOS_SIZE)
+            },
+        }
+    }
+}
+
+impl fmt::Debug for BiosError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,832 @@
+use crate::core::bus::Memory;
+use crate::core::cpu::ARM7TDMI;
+use crate::constants::register_index;
+use crate::constants::flag_masks;
+use crate::constants::io_address;
+use crate::constants::bios_address;
+use crate::constants::memory_region;
+use crate::constants::exception_vector;
+use crate::constants::BIOS_SIZE;
+use crate::constants::EWRAM_SIZE;
+use crate::constants::PALETTE_SIZE;
+use crate::constants::VRAM_SIZE;
+use crate::constants::OAM_SIZE;
+
+pub mod swi {
+    pub const SOFT_RESET: u8 = 0x00;
+    pub const REGISTER_RAM_RESET: u8 = 0x01;
+    pub const HALT: u8 = 0x02;
+    pub const STOP: u8 = 0x03;
+    pub const INTR_WAIT: u8 = 0x04;
+    pub const VBLANK_INTR_WAIT: u8 = 0x05;
+    pub const DIV: u8 = 0x06;
+    pub const DIV_ARM: u8 = 0x07;
+    pub const SQRT: u8 = 0x08;
+    pub const ARC_TAN: u8 = 0x09;
+    pub const ARC_TAN2: u8 = 0x0A;
+    pub const CPU_SET: u8 = 0x0B;
+    pub const CPU_FAST_SET: u8 = 0x0C;
+    pub const BG_AFFINE_SET: u8 = 0x0E;
+    pub const OBJ_AFFINE_SET: u8 = 0x0F;
+    pub const BIT_UNPACK: u8 = 0x10;
+    pub const LZ77_UNCOMP_WRAM: u8 = 0x11;
+    pub const LZ77_UNCOMP_VRAM: u8 = 0x12;
+    pub const HUFF_UNCOMP: u8 = 0x13;
+    pub const RL_UNCOMP_WRAM: u8 = 0x14;
+    pub const RL_UNCOMP_VRAM: u8 = 0x15;
+    pub const DIFF8_UNFILTER_WRAM: u8 = 0x16;
+    pub const DIFF8_UNFILTER_VRAM: u8 = 0x17;
+    pub const DIFF16_UNFILTER: u8 = 0x18;
+    pub const SOUND_BIAS: u8 = 0x19;
+    pub const MIDI_KEY_2_FREQ: u8 = 0x1F;
+}
+
+// the irq vector and the bios dispatcher behind it, which saves the
+// registers a handler may clobber and calls the game's handler through the
+// pointer at 0x0300_7FFC. every other swi is handled without running code
+const IRQ_DISPATCHER: usize = 0x128;
+const IRQ_STUB: [u32; 6] = [
+    0xE92D_500F, // stmfd sp!, {r0-r3, r12, lr}
+    0xE3A0_0301, // mov r0, #0x04000000
+    0xE28F_E000, // add lr, pc, #0
+    0xE510_F004, // ldr pc, [r0, #-4], 0x0300_7FFC through the iwram mirror
+    0xE8BD_500F, // ldmfd sp!, {r0-r3, r12, lr}
+    0xE25E_F004, // subs pc, lr, #4
+];
+
+// a stand in bios image for booting without one
+pub fn stub_image() -> Vec<u8> {
+    let mut image = vec![0u8; BIOS_SIZE];
+
+    // b 0x128
+    let branch = 0xEA00_0000 | ((IRQ_DISPATCHER as u32 - exception_vector::IRQ - 8) >> 2);
+    let vector = exception_vector::IRQ as usize;
+    image[vector..vector + 4].copy_from_slice(&branch.to_le_bytes());
+
+    for (i, opcode) in IRQ_STUB.iter().enumerate() {
+        let addr = IRQ_DISPATCHER + i * 4;
+        image[addr..addr + 4].copy_from_slice(&opcode.to_le_bytes());
+    }
+
+    image
+}
+
+// runs the bios function in place of the real handler. expects pc to already
+// point past the swi instruction, the same as the return address would
+pub fn software_interrupt(cpu: &mut ARM7TDMI, memory: &mut Memory, comment: u8) {
+    match comment {
+        swi::SOFT_RESET => soft_reset(cpu, memory),
+        swi::REGISTER_RAM_RESET => register_ram_reset(memory, cpu.rreg(0)),
+        swi::HALT => memory.wbyte(io_address::HALTCNT, 0x00),
+        swi::STOP => memory.wbyte(io_address::HALTCNT, 0x80),
+        swi::INTR_WAIT => intr_wait(cpu, memory),
+        swi::VBLANK_INTR_WAIT => {
+            cpu.wreg(0, 1);
+            cpu.wreg(1, 1);
+            intr_wait(cpu, memory);
+        },
+        swi::DIV => div(cpu, cpu.rreg(0) as i32, cpu.rreg(1) as i32),
+        swi::DIV_ARM => div(cpu, cpu.rreg(1) as i32, cpu.rreg(0) as i32),
+        swi::SQRT => cpu.wreg(0, sqrt(cpu.rreg(0))),
+        swi::ARC_TAN => {
+            let (angle, a, b) = arc_tan(cpu.rreg(0) as i32);
+            cpu.wreg(0, angle as u32);
+            cpu.wreg(1, a as u32);
+            cpu.wreg(3, b as u32);
+        },
+        swi::ARC_TAN2 => {
+            let (angle, a) = arc_tan2(cpu.rreg(0) as i32, cpu.rreg(1) as i32);
+            cpu.wreg(0, angle as u32);
+            cpu.wreg(1, a as u32);
+            cpu.wreg(3, 0x170);
+        },
+        swi::CPU_SET => cpu_set(memory, cpu.rreg(0), cpu.rreg(1), cpu.rreg(2)),
+        swi::CPU_FAST_SET => cpu_fast_set(memory, cpu.rreg(0), cpu.rreg(1), cpu.rreg(2)),
+        swi::BG_AFFINE_SET => bg_affine_set(memory, cpu.rreg(0), cpu.rreg(1), cpu.rreg(2)),
+        swi::OBJ_AFFINE_SET => obj_affine_set(memory, cpu.rreg(0), cpu.rreg(1), cpu.rreg(2), cpu.rreg(3)),
+        swi::BIT_UNPACK => bit_unpack(memory, cpu.rreg(0), cpu.rreg(1), cpu.rreg(2)),
+        swi::LZ77_UNCOMP_WRAM => lz77_uncomp(memory, cpu.rreg(0), cpu.rreg(1), false),
+        swi::LZ77_UNCOMP_VRAM => lz77_uncomp(memory, cpu.rreg(0), cpu.rreg(1), true),
+        swi::HUFF_UNCOMP => huff_uncomp(memory, cpu.rreg(0), cpu.rreg(1)),
+        swi::RL_UNCOMP_WRAM => rl_uncomp(memory, cpu.rreg(0), cpu.rreg(1), false),
+        swi::RL_UNCOMP_VRAM => rl_uncomp(memory, cpu.rreg(0), cpu.rreg(1), true),
+        swi::DIFF8_UNFILTER_WRAM => diff_unfilter(memory, cpu.rreg(0), cpu.rreg(1), 1, 1),
+        swi::DIFF8_UNFILTER_VRAM => diff_unfilter(memory, cpu.rreg(0), cpu.rreg(1), 1, 2),
+        swi::DIFF16_UNFILTER => diff_unfilter(memory, cpu.rreg(0), cpu.rreg(1), 2, 2),
+        swi::SOUND_BIAS => sound_bias(memory, cpu.rreg(0)),
+        swi::MIDI_KEY_2_FREQ => {
+            let freq = midi_key_2_freq(memory, cpu.rreg(0), cpu.rreg(1), cpu.rreg(2));
+            cpu.wreg(0, freq);
+        },
+        _ => {},
+    }
+}
+
+fn soft_reset(cpu: &mut ARM7TDMI, memory: &mut Memory) {
+    let multiboot = memory.rbyte(bios_address::MULTIBOOT_FLAG) != 0;
+
+    for addr in bios_address::RESERVED_AREA..memory_region::IWRAM + 0x8000 {
+        memory.wbyte(addr, 0);
+    }
+
+    *cpu = ARM7TDMI::default();
+    cpu.register[register_index::PC] = if multiboot { memory_region::EWRAM as u32 } else { memory_region::ROM as u32 };
+}
+
+fn register_ram_reset(memory: &mut Memory, flags: u32) {
+    memory.whalf(io_address::DISPCNT, 0x0080);
+
+    if (flags & 0x01) != 0 {
+        clear(memory, memory_region::EWRAM, EWRAM_SIZE);
+    }
+    // the top 0x200 bytes of iwram hold the stacks and bios variables
+    if (flags & 0x02) != 0 {
+        clear(memory, memory_region::IWRAM, 0x7E00);
+    }
+    if (flags & 0x04) != 0 {
+        clear(memory, memory_region::PALETTE, PALETTE_SIZE);
+    }
+    if (flags & 0x08) != 0 {
+        clear(memory, memory_region::VRAM, VRAM_SIZE);
+    }
+    if (flags & 0x10) != 0 {
+        clear(memory, memory_region::OAM, OAM_SIZE);
+    }
+    if (flags & 0x20) != 0 {
+        clear(memory, memory_region::IO + 0x120, 0x10);
+        clear(memory, memory_region::IO + 0x140, 0x20);
+    }
+    if (flags & 0x40) != 0 {
+        clear(memory, memory_region::IO + 0x60, 0x28);
+        clear(memory, memory_region::IO + 0x8A, 0x1E);
+    }
+    if (flags & 0x80) != 0 {
+        clear(memory, memory_region::IO + 0x02, 0x5E);
+        clear(memory, memory_region::IO + 0xB0, 0x70);
+        clear(memory, memory_region::IO + 0x200, 0x0C);
+    }
+}
+
+fn clear(memory: &mut Memory, start: usize, length: usize) {
+    for addr in start..start + length {
+        memory.wbyte(addr, 0);
+    }
+}
+
+// the bios keeps its own copy of the acknowledged irqs, which the game's
+// handler is expected to or into. until one of the wanted flags shows up the
+// swi is rewound so it runs again once the halt is broken by an interrupt
+fn intr_wait(cpu: &mut ARM7TDMI, memory: &mut Memory) {
+    let wanted = (cpu.rreg(1) & 0x3FFF) as u16;
+    let mut bios_if = memory.rhalf(bios_address::IF);
+
+    memory.whalf(io_address::IME, 1);
+
+    if cpu.rreg(0) != 0 {
+        bios_if &= !wanted;
+        memory.whalf(bios_address::IF, bios_if);
+        cpu.wreg(0, 0);
+    }
+
+    if (bios_if & wanted) != 0 {
+        memory.whalf(bios_address::IF, bios_if & !wanted);
+        return;
+    }
+
+    let thumb = (cpu.register[register_index::CPSR] & flag_masks::T) != 0;
+    let length = if thumb { 2 } else { 4 };
+    cpu.register[register_index::PC] = cpu.register[register_index::PC].wrapping_sub(length);
+    memory.wbyte(io_address::HALTCNT, 0x00);
+}
+
+fn div(cpu: &mut ARM7TDMI, numerator: i32, denominator: i32) {
+    if denominator == 0 {
+        // the real routine never terminates here, these are the values it
+        // would be holding if it did
+        cpu.wreg(0, if numerator < 0 { -1i32 as u32 } else { 1 });
+        cpu.wreg(1, numerator as u32);
+        cpu.wreg(3, 1);
+        return;
+    }
+
+    let quotient = numerator.wrapping_div(denominator);
+    cpu.wreg(0, quotient as u32);
+    cpu.wreg(1, numerator.wrapping_rem(denominator) as u32);
+    cpu.wreg(3, quotient.unsigned_abs());
+}
+
+fn sqrt(value: u32) -> u32 {
+    let mut root: u32 = 0;
+    let mut bit: u32 = 1 << 30;
+    let mut rest = value;
+
+    while bit > rest {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if rest >= root + bit {
+            rest -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    root
+}
+
+// polynomial approximation on a 1.14 tangent, the same sequence of
+// multiplies the bios does so rounding matches
+fn arc_tan(tan: i32) -> (i32, i32, i32) {
+    let a = -(tan.wrapping_mul(tan) >> 14);
+    let mut b = (0xA9i32.wrapping_mul(a) >> 14) + 0x390;
+
+    for constant in [0x91C, 0xFB6, 0x16AA, 0x2081, 0x3651, 0xA2F9] {
+        b = (b.wrapping_mul(a) >> 14) + constant;
+    }
+
+    (tan.wrapping_mul(b) >> 16, a, b)
+}
+
+// x and y come in r0 and r1. on an axis the bios returns before touching
+// r1, so it's handed back still holding y
+fn arc_tan2(x: i32, y: i32) -> (u16, i32) {
+    if y == 0 {
+        return (if x >= 0 { 0 } else { 0x8000 }, y);
+    }
+    if x == 0 {
+        return (if y >= 0 { 0x4000 } else { 0xC000 }, y);
+    }
+
+    let over_x = |offset: i32| {
+        let (angle, a, _) = arc_tan(y.wrapping_shl(14).wrapping_div(x));
+        ((angle + offset) as u16, a)
+    };
+    let over_y = |offset: i32| {
+        let (angle, a, _) = arc_tan(x.wrapping_shl(14).wrapping_div(y));
+        ((offset - angle) as u16, a)
+    };
+
+    if y >= 0 {
+        if x >= 0 {
+            if x >= y {
+                return over_x(0);
+            }
+        } else if -x >= y {
+            return over_x(0x8000);
+        }
+        over_y(0x4000)
+    } else {
+        if x <= 0 {
+            if -x > -y {
+                return over_x(0x8000);
+            }
+        } else if x >= -y {
+            return over_x(0x1_0000);
+        }
+        over_y(0xC000)
+    }
+}
+
+fn valid_source(src: u32) -> bool {
+    (src & 0x0E00_0000) != 0
+}
+
+fn cpu_set(memory: &mut Memory, src: u32, dst: u32, control: u32) {
+    if !valid_source(src) {
+        return;
+    }
+
+    let count = (control & 0x1F_FFFF) as usize;
+    let fill = (control & (1 << 24)) != 0;
+    let word = (control & (1 << 26)) != 0;
+
+    if word {
+        let (src, dst) = ((src & !3) as usize, (dst & !3) as usize);
+        let value = memory.rword(src);
+        for i in 0..count {
+            let data = if fill { value } else { memory.rword(src + i * 4) };
+            memory.wword(dst + i * 4, data);
+        }
+    } else {
+        let (src, dst) = ((src & !1) as usize, (dst & !1) as usize);
+        let value = memory.rhalf(src);
+        for i in 0..count {
+            let data = if fill { value } else { memory.rhalf(src + i * 2) };
+            memory.whalf(dst + i * 2, data);
+        }
+    }
+}
+
+// always moves whole words, eight at a time
+fn cpu_fast_set(memory: &mut Memory, src: u32, dst: u32, control: u32) {
+    if !valid_source(src) {
+        return;
+    }
+
+    let count = ((control & 0x1F_FFFF) as usize + 7) & !7;
+    let fill = (control & (1 << 24)) != 0;
+    let (src, dst) = ((src & !3) as usize, (dst & !3) as usize);
+    let value = memory.rword(src);
+
+    for i in 0..count {
+        let data = if fill { value } else { memory.rword(src + i * 4) };
+        memory.wword(dst + i * 4, data);
+    }
+}
+
+// the first quarter of the bios sine table, in 1.14 and rounded down. the
+// rest of the period mirrors it
+const SINE_TABLE: [i32; 65] = [
+    0x0000, 0x0192, 0x0323, 0x04B5, 0x0645, 0x07D5, 0x0964, 0x0AF1,
+    0x0C7C, 0x0E05, 0x0F8C, 0x1111, 0x1294, 0x1413, 0x158F, 0x1708,
+    0x187D, 0x19EF, 0x1B5D, 0x1CC6, 0x1E2B, 0x1F8B, 0x20E7, 0x223D,
+    0x238E, 0x24DA, 0x261F, 0x275F, 0x2899, 0x29CD, 0x2AFA, 0x2C21,
+    0x2D41, 0x2E5A, 0x2F6B, 0x3076, 0x3179, 0x3274, 0x3367, 0x3453,
+    0x3536, 0x3612, 0x36E5, 0x37AF, 0x3871, 0x392A, 0x39DA, 0x3A82,
+    0x3B20, 0x3BB6, 0x3C42, 0x3CC5, 0x3D3E, 0x3DAE, 0x3E14, 0x3E71,
+    0x3EC5, 0x3F0E, 0x3F4E, 0x3F84, 0x3FB1, 0x3FD3, 0x3FEC, 0x3FFB,
+    0x4000,
+];
+
+fn sine(angle: u8) -> i32 {
+    let quarter = (angle & 0x3F) as usize;
+    let index = if (angle & 0x40) != 0 { 64 - quarter } else { quarter };
+    let magnitude = SINE_TABLE[index];
+
+    if (angle & 0x80) != 0 { -magnitude } else { magnitude }
+}
+
+fn cosine(angle: u8) -> i32 {
+    sine(angle.wrapping_add(0x40))
+}
+
+fn bg_affine_set(memory: &mut Memory, src: u32, dst: u32, count: u32) {
+    let (mut src, mut dst) = (src as usize, dst as usize);
+
+    for _ in 0..count {
+        let origin_x = memory.rword(src) as i32;
+        let origin_y = memory.rword(src + 4) as i32;
+        let center_x = memory.rhalf(src + 8) as i16 as i32;
+        let center_y = memory.rhalf(src + 10) as i16 as i32;
+        let scale_x = memory.rhalf(src + 12) as i16 as i32;
+        let scale_y = memory.rhalf(src + 14) as i16 as i32;
+        let angle = (memory.rhalf(src + 16) >> 8) as u8;
+
+        let (sin, cos) = (sine(angle), cosine(angle));
+        let pa = scale_x.wrapping_mul(cos) >> 14;
+        let pb = -(scale_x.wrapping_mul(sin) >> 14);
+        let pc = scale_y.wrapping_mul(sin) >> 14;
+        let pd = scale_y.wrapping_mul(cos) >> 14;
+
+        let x = origin_x.wrapping_sub(pa.wrapping_mul(center_x).wrapping_add(pb.wrapping_mul(center_y)));
+        let y = origin_y.wrapping_sub(pc.wrapping_mul(center_x).wrapping_add(pd.wrapping_mul(center_y)));
+
+        memory.whalf(dst, pa as u16);
+        memory.whalf(dst + 2, pb as u16);
+        memory.whalf(dst + 4, pc as u16);
+        memory.whalf(dst + 6, pd as u16);
+        memory.wword(dst + 8, x as u32);
+        memory.wword(dst + 12, y as u32);
+
+        src += 20;
+        dst += 16;
+    }
+}
+
+fn obj_affine_set(memory: &mut Memory, src: u32, dst: u32, count: u32, stride: u32) {
+    let (mut src, mut dst, stride) = (src as usize, dst as usize, stride as usize);
+
+    for _ in 0..count {
+        let scale_x = memory.rhalf(src) as i16 as i32;
+        let scale_y = memory.rhalf(src + 2) as i16 as i32;
+        let angle = (memory.rhalf(src + 4) >> 8) as u8;
+
+        let (sin, cos) = (sine(angle), cosine(angle));
+        let pa = scale_x.wrapping_mul(cos) >> 14;
+        let pb = -(scale_x.wrapping_mul(sin) >> 14);
+        let pc = scale_y.wrapping_mul(sin) >> 14;
+        let pd = scale_y.wrapping_mul(cos) >> 14;
+
+        memory.whalf(dst, pa as u16);
+        memory.whalf(dst + stride, pb as u16);
+        memory.whalf(dst + stride * 2, pc as u16);
+        memory.whalf(dst + stride * 3, pd as u16);
+
+        src += 8;
+        dst += stride * 4;
+    }
+}
+
+fn bit_unpack(memory: &mut Memory, src: u32, dst: u32, info: u32) {
+    let info = info as usize;
+    let mut length = memory.rhalf(info) as u32;
+    let src_width = memory.rbyte(info + 2) as u32;
+    let dst_width = memory.rbyte(info + 3) as u32;
+    let offset = memory.rword(info + 4);
+
+    if !matches!(src_width, 1 | 2 | 4 | 8) || !matches!(dst_width, 1 | 2 | 4 | 8 | 16 | 32) {
+        return;
+    }
+
+    let (mut src, mut dst) = (src as usize, dst as usize);
+    let mask = (1u32 << src_width) - 1;
+    let mut input: u32 = 0;
+    let mut input_bits: u32 = 0;
+    let mut output: u32 = 0;
+    let mut output_bits: u32 = 0;
+
+    while length > 0 || input_bits > 0 {
+        if input_bits == 0 {
+            input = memory.rbyte(src) as u32;
+            input_bits = 8;
+            src += 1;
+            length -= 1;
+        }
+
+        let mut value = input & mask;
+        input >>= src_width;
+        input_bits -= src_width;
+
+        // bit 31 of the offset says whether zeroes get the offset added too
+        if value != 0 || (offset & (1 << 31)) != 0 {
+            value = value.wrapping_add(offset & 0x7FFF_FFFF);
+        }
+
+        output |= value.wrapping_shl(output_bits);
+        output_bits += dst_width;
+
+        if output_bits == 32 {
+            memory.wword(dst, output);
+            dst += 4;
+            output = 0;
+            output_bits = 0;
+        }
+    }
+}
+
+// vram only takes halfword writes, so bytes are paired up before storing.
+// a trailing odd byte never makes it out, as on hardware
+fn write_output(memory: &mut Memory, dst: u32, data: &[u8], vram: bool) {
+    let dst = dst as usize;
+
+    if vram {
+        for (i, pair) in data.chunks_exact(2).enumerate() {
+            memory.whalf(dst + i * 2, u16::from_le_bytes([pair[0], pair[1]]));
+        }
+    } else {
+        for (i, byte) in data.iter().enumerate() {
+            memory.wbyte(dst + i, *byte);
+        }
+    }
+}
+
+fn lz77_uncomp(memory: &mut Memory, src: u32, dst: u32, vram: bool) {
+    if !valid_source(src) {
+        return;
+    }
+
+    let mut src = (src & !3) as usize;
+    let size = (memory.rword(src) >> 8) as usize;
+    let mut data: Vec<u8> = Vec::with_capacity(size);
+    src += 4;
+
+    while data.len() < size {
+        let flags = memory.rbyte(src);
+        src += 1;
+
+        for bit in (0..8).rev() {
+            if data.len() >= size {
+                break;
+            }
+
+            if (flags >> bit) & 1 == 0 {
+                data.push(memory.rbyte(src));
+                src += 1;
+            } else {
+                let high = memory.rbyte(src) as usize;
+                let low = memory.rbyte(src + 1) as usize;
+                src += 2;
+
+                let length = (high >> 4) + 3;
+                let disp = (((high & 0xF) << 8) | low) + 1;
+
+                for _ in 0..length {
+                    if data.len() >= size {
+                        break;
+                    }
+                    let byte = if disp <= data.len() { data[data.len() - disp] } else { 0 };
+                    data.push(byte);
+                }
+            }
+        }
+    }
+
+    write_output(memory, dst, &data, vram);
+}
+
+fn huff_uncomp(memory: &mut Memory, src: u32, dst: u32) {
+    if !valid_source(src) {
+        return;
+    }
+
+    let src = (src & !3) as usize;
+    let header = memory.rword(src);
+    let mut remaining = (header >> 8) as i64;
+    let bits = match header & 0xF {
+        0 => 8,
+        bits => bits,
+    };
+
+    if 32 % bits != 0 || bits == 1 {
+        return;
+    }
+
+    let tree_base = src + 5;
+    let tree_size = ((memory.rbyte(src + 4) as usize) << 1) + 1;
+    let mut stream = tree_base + tree_size;
+    let mut dst = dst as usize;
+
+    let mut node_addr = tree_base;
+    let mut node = memory.rbyte(node_addr);
+    let mut block: u32 = 0;
+    let mut block_bits: u32 = 0;
+
+    while remaining > 0 {
+        let mut bitstream = memory.rword(stream);
+        stream += 4;
+
+        for _ in 0..32 {
+            if remaining <= 0 {
+                break;
+            }
+
+            // bits 0-5 are the offset to the child pair, bits 6 and 7 say
+            // whether the right and left children are leaves
+            let next = (node_addr & !1) + ((node & 0x3F) as usize) * 2 + 2;
+            let right = (bitstream & (1 << 31)) != 0;
+            bitstream <<= 1;
+
+            let (child, leaf) = if right { (next + 1, (node & 0x40) != 0) } else { (next, (node & 0x80) != 0) };
+
+            if !leaf {
+                node_addr = child;
+                node = memory.rbyte(node_addr);
+                continue;
+            }
+
+            let symbol = memory.rbyte(child) as u32 & ((1 << bits) - 1);
+            block |= symbol << block_bits;
+            block_bits += bits;
+            node_addr = tree_base;
+            node = memory.rbyte(node_addr);
+
+            if block_bits == 32 {
+                memory.wword(dst, block);
+                dst += 4;
+                remaining -= 4;
+                block = 0;
+                block_bits = 0;
+            }
+        }
+    }
+}
+
+fn rl_uncomp(memory: &mut Memory, src: u32, dst: u32, vram: bool) {
+    if !valid_source(src) {
+        return;
+    }
+
+    let mut src = (src & !3) as usize;
+    let size = (memory.rword(src) >> 8) as usize;
+    let mut data: Vec<u8> = Vec::with_capacity(size + 3);
+    src += 4;
+
+    while data.len() < size {
+        let flag = memory.rbyte(src);
+        src += 1;
+
+        if (flag & 0x80) != 0 {
+            let byte = memory.rbyte(src);
+            src += 1;
+            for _ in 0..((flag & 0x7F) as usize + 3) {
+                if data.len() < size {
+                    data.push(byte);
+                }
+            }
+        } else {
+            for _ in 0..(flag as usize + 1) {
+                if data.len() < size {
+                    data.push(memory.rbyte(src));
+                    src += 1;
+                }
+            }
+        }
+    }
+
+    // the output is padded with zeroes out to a word boundary
+    while !data.len().is_multiple_of(4) {
+        data.push(0);
+    }
+
+    write_output(memory, dst, &data, vram);
+}
+
+fn diff_unfilter(memory: &mut Memory, src: u32, dst: u32, in_width: usize, out_width: usize) {
+    if !valid_source(src) {
+        return;
+    }
+
+    let mut src = (src & !3) as usize;
+    let size = (memory.rword(src) >> 8) as usize;
+    let mut data: Vec<u8> = Vec::with_capacity(size);
+    let mut old: u16 = 0;
+    src += 4;
+
+    while data.len() < size {
+        if in_width == 1 {
+            let new = (memory.rbyte(src) as u16).wrapping_add(old) & 0xFF;
+            data.push(new as u8);
+            old = new;
+        } else {
+            let new = memory.rhalf(src).wrapping_add(old);
+            data.extend_from_slice(&new.to_le_bytes());
+            old = new;
+        }
+        src += in_width;
+    }
+
+    write_output(memory, dst, &data, out_width == 2);
+}
+
+// ramps the bias level to 0x000 or 0x200; the bios does it a step at a time
+// but nothing can observe the ramp without sound hardware in between
+fn sound_bias(memory: &mut Memory, level: u32) {
+    let bias = memory.rhalf(io_address::SOUNDBIAS);
+    let level: u16 = if level != 0 { 0x200 } else { 0x000 };
+    memory.whalf(io_address::SOUNDBIAS, (bias & 0xFC01) | level);
+}
+
+// 2^31 raised by each semitone of an octave
+const FREQ_TABLE: [u32; 12] = [
+    2147483648, 2275179671, 2410468894, 2553802834, 2705659852, 2866546760,
+    3037000500, 3217589947, 3408917802, 3611622603, 3826380858, 4053909305,
+];
+
+// the wave's rate scaled by 2^((key - 180) / 12). the bios takes each key's
+// scale from the semitone table shifted down by octave, and moves linearly
+// towards the next key's by the fine adjustment in 256ths. the highest keys
+// stop at just under 179
+fn midi_key_2_freq(memory: &Memory, wave: u32, key: u32, fine: u32) -> u32 {
+    let (key, fine) = match key & 0xFF {
+        key if key > 178 => (178, 0xFF),
+        key => (key, fine & 0xFF),
+    };
+
+    let scale = |key: u32| FREQ_TABLE[(key % 12) as usize] >> (14 - key / 12);
+    let (low, high) = (scale(key), scale(key + 1));
+    let frequency = memory.rword(wave as usize + 4);
+
+    mul_high(frequency, low + mul_high(high - low, fine << 24))
+}
+
+// the top half of a 32 by 32 bit multiply, as umull leaves it
+fn mul_high(a: u32, b: u32) -> u32 {
+    ((a as u64 * b as u64) >> 32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: usize = memory_region::EWRAM;
+    const DST: usize = memory_region::IWRAM;
+
+    fn memory_with(data: &[u8]) -> Memory {
+        let mut memory = Memory::new();
+        for (i, byte) in data.iter().enumerate() {
+            memory.wbyte(SRC + i, *byte);
+        }
+        memory
+    }
+
+    fn output(memory: &Memory, length: usize) -> Vec<u8> {
+        (0..length).map(|i| memory.rbyte(DST + i)).collect()
+    }
+
+    #[test]
+    fn div_rounds_towards_zero() {
+        let mut cpu = ARM7TDMI::default();
+
+        div(&mut cpu, 7, 2);
+        assert_eq!([cpu.rreg(0), cpu.rreg(1), cpu.rreg(3)], [3, 1, 3]);
+
+        div(&mut cpu, -7, 2);
+        assert_eq!([cpu.rreg(0) as i32, cpu.rreg(1) as i32, cpu.rreg(3) as i32], [-3, -1, 3]);
+
+        div(&mut cpu, i32::MIN, -1);
+        assert_eq!([cpu.rreg(0), cpu.rreg(1), cpu.rreg(3)], [0x8000_0000, 0, 0x8000_0000]);
+    }
+
+    #[test]
+    fn sqrt_rounds_down() {
+        assert_eq!(sqrt(0), 0);
+        assert_eq!(sqrt(2), 1);
+        assert_eq!(sqrt(16), 4);
+        assert_eq!(sqrt(99), 9);
+        assert_eq!(sqrt(u32::MAX), 0xFFFF);
+    }
+
+    #[test]
+    fn arc_tan_matches_bios() {
+        assert_eq!(arc_tan(0), (0, 0, 0xA2F9));
+        assert_eq!(arc_tan(0x4000), (0x2000, -0x4000, 0x8000));
+        assert_eq!(arc_tan(0x2000), (0x12E4, -0x1000, 0x9720));
+        assert_eq!(arc_tan(-0x2000), (-0x12E4, -0x1000, 0x9720));
+        assert_eq!(arc_tan(0x1000), (0x9FB, -0x400, 0x9FB3));
+    }
+
+    #[test]
+    fn arc_tan2_covers_every_octant() {
+        assert_eq!(arc_tan2(0x100, 0).0, 0);
+        assert_eq!(arc_tan2(-0x100, 0).0, 0x8000);
+        assert_eq!(arc_tan2(0, 0x100).0, 0x4000);
+        assert_eq!(arc_tan2(0, -0x100).0, 0xC000);
+        assert_eq!(arc_tan2(0x100, 0x100).0, 0x2000);
+        assert_eq!(arc_tan2(-0x100, 0x100).0, 0x6000);
+        assert_eq!(arc_tan2(-0x100, -0x100).0, 0xA000);
+        assert_eq!(arc_tan2(0x300, 0x100).0, 0x0D1B);
+        assert_eq!(arc_tan2(0x100, -0x200).0, 0xD2E4);
+    }
+
+    #[test]
+    fn arc_tan2_takes_x_and_y_from_r0_and_r1() {
+        let mut cpu = ARM7TDMI::default();
+        let mut memory = Memory::new();
+
+        cpu.wreg(0, 0x100);
+        cpu.wreg(1, -0x200i32 as u32);
+        software_interrupt(&mut cpu, &mut memory, swi::ARC_TAN2);
+        assert_eq!(cpu.rreg(0), 0xD2E4);
+
+        cpu.wreg(0, 0);
+        cpu.wreg(1, 0x100);
+        software_interrupt(&mut cpu, &mut memory, swi::ARC_TAN2);
+        assert_eq!([cpu.rreg(0), cpu.rreg(1)], [0x4000, 0x100]);
+    }
+
+    #[test]
+    fn obj_affine_set_uses_the_bios_table() {
+        // x scaled by 1/64 and y by 1 at angle 2, where the table rounds down
+        // from 0x324
+        let mut memory = memory_with(&[0x00, 0x01, 0x00, 0x40, 0x00, 0x02, 0x00, 0x00]);
+        obj_affine_set(&mut memory, SRC as u32, DST as u32, 1, 2);
+
+        let params: Vec<u16> = (0..4).map(|i| memory.rhalf(DST + i * 2)).collect();
+        assert_eq!(params, [0x00FF, 0xFFF4, 0x0323, 0x3FEC]);
+    }
+
+    #[test]
+    fn lz77_copies_back_references() {
+        // "ABC" then six bytes from three back
+        let mut memory = memory_with(&[0x10, 0x09, 0x00, 0x00, 0x10, b'A', b'B', b'C', 0x30, 0x02]);
+        lz77_uncomp(&mut memory, SRC as u32, DST as u32, false);
+
+        assert_eq!(output(&memory, 10), b"ABCABCABC\0");
+    }
+
+    #[test]
+    fn huffman_walks_the_tree() {
+        // a root whose children are both leaves, 'a' on 0 and 'b' on 1
+        let mut memory = memory_with(&[0x28, 0x04, 0x00, 0x00, 0x01, 0xC0, b'a', b'b', 0x00, 0x00, 0x00, 0x60]);
+        huff_uncomp(&mut memory, SRC as u32, DST as u32);
+
+        assert_eq!(output(&memory, 4), b"abba");
+    }
+
+    #[test]
+    fn rl_mixes_runs_and_literals() {
+        let mut memory = memory_with(&[0x30, 0x07, 0x00, 0x00, 0x02, b'x', b'y', b'z', 0x81, b'q']);
+        memory.wbyte(DST + 7, 0xFF);
+        rl_uncomp(&mut memory, SRC as u32, DST as u32, false);
+
+        assert_eq!(output(&memory, 8), b"xyzqqqq\0");
+    }
+
+    #[test]
+    fn midi_key_2_freq_matches_bios() {
+        // 13379 Hz in the sound driver's 22.10 format
+        let mut memory = memory_with(&[0; 4]);
+        memory.wword(SRC + 4, 13379 << 10);
+        let freq = |memory: &Memory, key, fine| midi_key_2_freq(memory, SRC as u32, key, fine);
+
+        assert_eq!(freq(&memory, 60, 0), 13379);
+        assert_eq!(freq(&memory, 69, 0), 22500);
+        assert_eq!(freq(&memory, 72, 128), 27553);
+        assert_eq!(freq(&memory, 0, 0), 418);
+        assert_eq!(freq(&memory, 127, 255), 679461);
+        assert_eq!(freq(&memory, 180, 0), 12928333);
+    }
+}
@@ -1,5 +1,61 @@
+use crate::core::apu::Apu;
+use crate::core::cartridge::Cartridge;
+use crate::core::dma;
+use crate::core::dma::AddressControl;
+use crate::core::dma::Dma;
+use crate::core::dma::Timing;
+use crate::core::ppu::Ppu;
+use crate::core::ppu::PpuEvent;
+use crate::core::timer::Timers;
+use crate::constants::memory_region;
+use crate::constants::io_address;
+use crate::constants::interrupt_flag;
+use crate::constants::dispstat;
+use crate::constants::dmacnt;
+use crate::constants::lcd;
+use crate::constants::BIOS_SIZE;
+use crate::constants::EWRAM_SIZE;
+use crate::constants::IWRAM_SIZE;
+
+const BIOS_END: usize = memory_region::BIOS + BIOS_SIZE - 1;
+// past the real ram each work ram repeats through the rest of its 16 MiB
+const EWRAM_MIRRORS: usize = memory_region::EWRAM + EWRAM_SIZE;
+const EWRAM_MIRRORS_END: usize = memory_region::IWRAM - 1;
+const IWRAM_MIRRORS: usize = memory_region::IWRAM + IWRAM_SIZE;
+const IWRAM_MIRRORS_END: usize = memory_region::IO - 1;
+const VCOUNT_HIGH: usize = io_address::VCOUNT + 1;
+const IF_HIGH: usize = io_address::IF + 1;
+const BG2X_END: usize = io_address::BG2X + 3;
+const BG2Y_END: usize = io_address::BG2Y + 3;
+const BG3X_END: usize = io_address::BG3X + 3;
+const BG3Y_END: usize = io_address::BG3Y + 3;
+const SOUND_END: usize = io_address::SOUNDCNT_X + 3;
+const FIFO_END: usize = io_address::FIFO_B + 3;
+const TIMER_END: usize = io_address::TM0CNT_L + 15;
+const DMA_END: usize = io_address::DMA0SAD + dma::CHANNEL_STRIDE * 4 - 1;
+// the lines dma3 copies during video capture, the last one switching it off
+const CAPTURE_START: u16 = 2;
+const CAPTURE_END: u16 = 162;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PowerState {
+    Running,
+    Halted,
+    Stopped,
+}
+
 pub struct Memory {
     ram: Box<[u8]>,
+    pub cartridge: Option<Cartridge>,
+    pub power_state: PowerState,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    pub timers: Timers,
+    pub dma: Dma,
+    // cycles the cpu owes for dma that ran, while it was kept off the bus
+    pub stall_cycles: u32,
+    // cycles run since power on, what everything with timing goes by
+    pub cycles: u64,
 }
 
 pub trait BusAccess {
@@ -9,15 +65,380 @@ pub trait BusAccess {
 
 impl Memory {
     pub fn new() -> Memory {
-        Memory {ram: vec![0u8; 0xFFFF_FFFF].into_boxed_slice(),}
+        Memory {
+            ram: vec![0u8; 0xFFFF_FFFF].into_boxed_slice(),
+            cartridge: None,
+            power_state: PowerState::Running,
+            ppu: Ppu::new(),
+            apu: Apu::new(),
+            timers: Timers::new(),
+            dma: Dma::new(),
+            stall_cycles: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = Some(cartridge);
+    }
+
+    // the backing memory as is, for looking at without side effects
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn load_bios(&mut self, image: &[u8]) {
+        self.ram[memory_region::BIOS..memory_region::BIOS + BIOS_SIZE].copy_from_slice(image);
+    }
+
+    // moves the hardware on by the cycles the cpu just spent, stopping at
+    // each timer overflow so whatever it drives happens on time
+    pub fn tick(&mut self, cycles: u32) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let step = remaining.min(self.timers.cycles_to_overflow(self.cycles));
+            remaining -= step;
+            self.cycles += step as u64;
+            self.apu.tick(step, &self.ram);
+
+            let mut video = step;
+            while video > 0 {
+                let (advanced, event) = self.ppu.advance(video);
+                video -= advanced;
+
+                if let Some(event) = event {
+                    self.ppu_event(event);
+                }
+            }
+
+            for timer in self.timers.overflows(self.cycles) {
+                self.timer_overflow(timer);
+            }
+
+            self.game_pak_dma();
+        }
+    }
+
+    fn ppu_event(&mut self, event: PpuEvent) {
+        let control = self.ram[io_address::DISPSTAT];
+        let vcount_setting = self.ram[io_address::DISPSTAT + 1];
+
+        match event {
+            PpuEvent::HBlank { line } => {
+                if line < lcd::HEIGHT as u16 {
+                    self.ppu.render_line(line, &self.ram);
+                    self.trigger_dma(Timing::HBlank);
+                }
+                if (control & dispstat::HBLANK_IRQ) != 0 {
+                    self.request_interrupt(interrupt_flag::HBLANK);
+                }
+            },
+            PpuEvent::LineStart { line } => {
+                if line == lcd::HEIGHT as u16 {
+                    self.ppu.latch_affine(0, &self.ram);
+                    self.ppu.latch_affine(1, &self.ram);
+                    self.trigger_dma(Timing::VBlank);
+                }
+                self.video_capture(line);
+                if line == lcd::HEIGHT as u16 && (control & dispstat::VBLANK_IRQ) != 0 {
+                    self.request_interrupt(interrupt_flag::VBLANK);
+                }
+                if line == vcount_setting as u16 && (control & dispstat::VCOUNT_IRQ) != 0 {
+                    self.request_interrupt(interrupt_flag::VCOUNT);
+                }
+            },
+        }
+    }
+
+    // timers 0 and 1 also clock the direct sound fifos
+    fn timer_overflow(&mut self, timer: usize) {
+        if self.timers.irq_enabled(timer) {
+            self.request_interrupt(interrupt_flag::TIMER0 << timer);
+        }
+        if timer < 2 {
+            self.apu.timer_overflow(timer, &self.ram);
+            self.refill_fifos();
+        }
+    }
+
+    // a channel's enable bit has just been set
+    fn enable_dma(&mut self, index: usize) {
+        self.dma.latch(index, &self.ram);
+
+        let control = dma::control(&self.ram, index);
+        if control.timing == Timing::Immediate && !waits_for_game_pak(index, &control) {
+            self.run_dma(index);
+        }
+    }
+
+    // runs every channel waiting on the event, lowest numbered first as
+    // that's the order of priority. transfers aren't broken up, so priority
+    // only decides the order when channels start together
+    fn trigger_dma(&mut self, timing: Timing) {
+        for index in 0..4 {
+            let control = dma::control(&self.ram, index);
+            if control.enabled && control.timing == timing && !waits_for_game_pak(index, &control) {
+                self.run_dma(index);
+            }
+        }
+    }
+
+    // the cartridge asking dma3 for a transfer, which only does anything
+    // while dma3 is set to wait for it
+    fn game_pak_dma(&mut self) {
+        let requested = match &mut self.cartridge {
+            Some(cartridge) => std::mem::take(&mut cartridge.dma_request),
+            None => false,
+        };
+
+        let control = dma::control(&self.ram, 3);
+        if requested && control.enabled && waits_for_game_pak(3, &control) {
+            self.run_dma(3);
+        }
+    }
+
+    // dma1 and 2 in special timing feed whichever fifo they point at
+    fn refill_fifos(&mut self) {
+        let requests = std::mem::take(&mut self.apu.fifo_requests);
+
+        for (fifo, addr) in [io_address::FIFO_A, io_address::FIFO_B].into_iter().enumerate() {
+            if (requests & (1 << fifo)) == 0 {
+                continue;
+            }
+
+            let channel = (1..3).find(|&index| {
+                let control = dma::control(&self.ram, index);
+                control.enabled && control.timing == Timing::Special && self.dma.channels[index].dest as usize == addr
+            });
+            if let Some(index) = channel {
+                self.run_dma(index);
+            }
+        }
+    }
+
+    // dma3 in special timing copies a line at a time from a camera or the
+    // like, through the middle of the frame, then turns itself off
+    fn video_capture(&mut self, line: u16) {
+        let control = dma::control(&self.ram, 3);
+        if !control.enabled || control.timing != Timing::Special || waits_for_game_pak(3, &control) {
+            return;
+        }
+
+        if (CAPTURE_START..CAPTURE_END).contains(&line) {
+            self.run_dma(3);
+        } else if line == CAPTURE_END {
+            self.ram[dma::register(3) + dma::CONTROL_OFFSET + 1] &= !((dmacnt::ENABLE >> 8) as u8);
+        }
+    }
+
+    // moves the whole transfer at once. a real channel would give way to a
+    // higher priority one triggered partway through, here they run in turn
+    fn run_dma(&mut self, index: usize) {
+        let control = dma::control(&self.ram, index);
+        // sound dma ignores the count and size and always sends four words
+        // to the same fifo address
+        let sound = control.timing == Timing::Special && (index == 1 || index == 2);
+        let (width, count) = match sound {
+            true => (4, dma::FIFO_WORDS),
+            false => (if control.word { 4 } else { 2 }, self.dma.channels[index].count),
+        };
+
+        if let (3, Some(cartridge)) = (index, &mut self.cartridge) {
+            let channel = &self.dma.channels[3];
+            cartridge.dma_transfer(channel.source as usize, count as usize);
+            cartridge.dma_transfer(channel.dest as usize, count as usize);
+        }
+
+        for _ in 0..count {
+            let channel = self.dma.channels[index];
+            if width == 4 {
+                let data = self.rword(channel.source as usize);
+                self.wword(channel.dest as usize, data);
+            } else {
+                let data = self.rhalf(channel.source as usize);
+                self.whalf(channel.dest as usize, data);
+            }
+
+            let (source_step, dest_step) = self.dma.steps(index, &control, width);
+            self.dma.advance(index, source_step, if sound { 0 } else { dest_step });
+        }
+
+        // a read and a write for each unit and two cycles getting going,
+        // leaving out the wait states of slow memory
+        self.stall_cycles += 2 + 2 * count;
+
+        if control.irq {
+            self.request_interrupt(interrupt_flag::DMA0 << index);
+        }
+
+        if control.repeat && control.timing != Timing::Immediate {
+            if !sound {
+                self.dma.reload_count(index, &self.ram);
+            }
+            if control.dest == AddressControl::Reload {
+                self.dma.reload_dest(index, &self.ram);
+            }
+        } else {
+            self.ram[dma::register(index) + dma::CONTROL_OFFSET + 1] &= !((dmacnt::ENABLE >> 8) as u8);
+        }
+    }
+
+    // sets the flag in IF and wakes a halted cpu if the interrupt is enabled,
+    // whether or not IME lets it through. stop only ends for the keypad,
+    // serial port or cartridge
+    pub fn request_interrupt(&mut self, flag: u16) {
+        let flags = u16::from_le_bytes([self.ram[io_address::IF], self.ram[io_address::IF + 1]]) | flag;
+        self.ram[io_address::IF..io_address::IF + 2].copy_from_slice(&flags.to_le_bytes());
+
+        let enabled = (self.rhalf(io_address::IE) & flag) != 0;
+        let wakes_stop = (flag & (interrupt_flag::KEYPAD | interrupt_flag::SERIAL | interrupt_flag::GAMEPAK)) != 0;
+
+        match self.power_state {
+            PowerState::Halted if enabled => self.power_state = PowerState::Running,
+            PowerState::Stopped if enabled && wakes_stop => self.power_state = PowerState::Running,
+            _ => {},
+        }
+    }
+
+    // an interrupt the cpu should take, unless it has irqs masked in cpsr
+    pub fn interrupt_pending(&self) -> bool {
+        (self.rhalf(io_address::IME) & 1) != 0 && (self.rhalf(io_address::IE) & self.rhalf(io_address::IF)) != 0
     }
 
     pub fn rbyte(&self, addr: usize) -> u8 {
-        self.ram[addr]
+        let addr = mirror(addr);
+        match addr {
+            io_address::DISPSTAT => (self.ram[addr] & !dispstat::STATUS_MASK) | self.ppu.status(self.ram[addr + 1]),
+            io_address::VCOUNT => self.ppu.vcount as u8,
+            VCOUNT_HIGH => 0,
+            io_address::SOUND1CNT_L..=SOUND_END | io_address::WAVE_RAM..=FIFO_END => self.apu.read(addr, &self.ram),
+            io_address::TM0CNT_L..=TIMER_END => self.timers.read(addr, self.cycles),
+            io_address::DMA0SAD..=DMA_END => dma::read(addr, &self.ram),
+            memory_region::ROM..=memory_region::SRAM_END => {
+                match &self.cartridge {
+                    Some(cartridge) => cartridge.rbyte(addr),
+                    None => self.ram[addr],
+                }
+            },
+            _ => self.ram[addr],
+        }
     }
 
     pub fn wbyte(&mut self, addr: usize, data: u8) {
-        self.ram[addr] = data;
+        let addr = mirror(addr);
+        match addr {
+            memory_region::BIOS..=BIOS_END => {},
+            io_address::DISPSTAT => self.ram[addr] = data & !dispstat::STATUS_MASK,
+            io_address::VCOUNT | VCOUNT_HIGH => {},
+            // writing a 1 acknowledges that interrupt
+            io_address::IF | IF_HIGH => self.ram[addr] &= !data,
+            // a new reference point takes effect straight away, mid frame
+            io_address::BG2X..=BG2X_END => {
+                self.ram[addr] = data;
+                self.ppu.latch_affine_x(0, &self.ram);
+            },
+            io_address::BG2Y..=BG2Y_END => {
+                self.ram[addr] = data;
+                self.ppu.latch_affine_y(0, &self.ram);
+            },
+            io_address::BG3X..=BG3X_END => {
+                self.ram[addr] = data;
+                self.ppu.latch_affine_x(1, &self.ram);
+            },
+            io_address::BG3Y..=BG3Y_END => {
+                self.ram[addr] = data;
+                self.ppu.latch_affine_y(1, &self.ram);
+            },
+            io_address::SOUND1CNT_L..=SOUND_END | io_address::WAVE_RAM..=FIFO_END => self.apu.write(addr, data, &mut self.ram),
+            io_address::TM0CNT_L..=TIMER_END => self.timers.write(addr, data, self.cycles),
+            io_address::DMA0SAD..=DMA_END => {
+                let index = (addr - io_address::DMA0SAD) / dma::CHANNEL_STRIDE;
+                let enabling = (addr - dma::register(index)) == dma::CONTROL_OFFSET + 1
+                    && (self.ram[addr] & data & ((dmacnt::ENABLE >> 8) as u8)) == 0
+                    && (data & ((dmacnt::ENABLE >> 8) as u8)) != 0;
+
+                self.ram[addr] = data;
+                if enabling {
+                    self.enable_dma(index);
+                }
+            },
+            io_address::HALTCNT => {
+                self.power_state = if (data & 0x80) == 0 { PowerState::Halted } else { PowerState::Stopped };
+            },
+            memory_region::ROM..=memory_region::SRAM_END => {
+                match &mut self.cartridge {
+                    Some(cartridge) => cartridge.wbyte(addr, data),
+                    None => self.ram[addr] = data,
+                }
+            },
+            _ => self.ram[addr] = data,
+        }
+    }
+
+    pub fn rhalf(&self, addr: usize) -> u16 {
+        let addr = addr & !1;
+        u16::from_le_bytes([self.rbyte(addr), self.rbyte(addr + 1)])
+    }
+
+    pub fn whalf(&mut self, addr: usize, data: u16) {
+        let addr = addr & !1;
+        let bytes = data.to_le_bytes();
+        self.wbyte(addr, bytes[0]);
+        self.wbyte(addr + 1, bytes[1]);
+    }
+
+    pub fn rword(&self, addr: usize) -> u32 {
+        let addr = addr & !3;
+        u32::from_le_bytes([self.rbyte(addr), self.rbyte(addr + 1), self.rbyte(addr + 2), self.rbyte(addr + 3)])
+    }
+
+    pub fn wword(&mut self, addr: usize, data: u32) {
+        let addr = addr & !3;
+        let bytes = data.to_le_bytes();
+        for (i, byte) in bytes.iter().enumerate() {
+            self.wbyte(addr + i, *byte);
+        }
+    }
+}
+
+// the bios irq handler relies on this, reading the game's handler from
+// 0x0400_0000 - 4 which lands on 0x0300_7FFC
+fn mirror(addr: usize) -> usize {
+    match addr {
+        EWRAM_MIRRORS..=EWRAM_MIRRORS_END => memory_region::EWRAM + (addr & (EWRAM_SIZE - 1)),
+        IWRAM_MIRRORS..=IWRAM_MIRRORS_END => memory_region::IWRAM + (addr & (IWRAM_SIZE - 1)),
+        _ => addr,
     }
 }
 
+// with the drq bit set dma3 only moves when the cartridge asks it to
+fn waits_for_game_pak(index: usize, control: &dma::Control) -> bool {
+    index == 3 && control.game_pak_drq
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_pak_drq_holds_dma3_until_the_cartridge_asks() {
+        let mut memory = Memory::new();
+        memory.load_cartridge(Cartridge::from_image(Vec::new()));
+        memory.wword(memory_region::EWRAM, 0x1234_5678);
+
+        let base = dma::register(3);
+        memory.wword(base, memory_region::EWRAM as u32);
+        memory.wword(base + 4, memory_region::IWRAM as u32);
+        memory.whalf(base + 8, 1);
+        memory.whalf(base + dma::CONTROL_OFFSET, dmacnt::ENABLE | dmacnt::WORD | dmacnt::GAME_PAK_DRQ);
+
+        memory.tick(1);
+        assert_eq!(memory.rword(memory_region::IWRAM), 0);
+
+        memory.cartridge.as_mut().unwrap().dma_request = true;
+        memory.tick(1);
+        assert_eq!(memory.rword(memory_region::IWRAM), 0x1234_5678);
+        assert!(!memory.cartridge.as_ref().unwrap().dma_request);
+        assert_eq!(memory.rhalf(base + dma::CONTROL_OFFSET) & dmacnt::ENABLE, 0);
+    }
+}
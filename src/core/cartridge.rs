@@ -0,0 +1,283 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::core::cartridge::backup::Backup;
+use crate::core::cartridge::backup::BackupType;
+use crate::core::cartridge::detect::DetectionSource;
+use crate::core::cartridge::gpio::Gpio;
+use crate::core::cartridge::gpio::rtc::Clock;
+use crate::core::cartridge::gpio::rtc::Rtc;
+use crate::core::cartridge::patch::PatchError;
+use crate::constants::cartridge_header;
+use crate::constants::memory_region;
+
+pub mod backup;
+pub mod detect;
+pub mod gpio;
+pub mod patch;
+pub mod save_file;
+
+pub struct Header {
+    // how far into the image the branch in the first word jumps, the same
+    // for a rom or a multiboot program
+    pub entry_offset: u32,
+    pub title: String,
+    pub game_code: String,
+    pub maker_code: String,
+    pub unit_code: u8,
+    pub software_version: u8,
+}
+
+pub struct Cartridge {
+    pub header: Header,
+    pub rom: Box<[u8]>,
+    pub backup: Backup,
+    pub gpio: Option<Gpio>,
+    pub patch: Option<PathBuf>,
+    // the request line on the cartridge edge, for hardware that streams data
+    // to dma3 rather than being read like rom. the bus takes it and clears it
+    pub dma_request: bool,
+}
+
+pub enum CartridgeError {
+    Io(io::Error),
+    TooSmall(usize),
+    TooLarge(usize),
+    ChecksumMismatch { expected: u8, computed: u8 },
+    PatchIo(PathBuf, io::Error),
+    Patch(PathBuf, PatchError),
+}
+
+impl Cartridge {
+    // applies the given patch, or one sitting next to the rom with the same
+    // name, before the header is checked
+    pub fn load(path: &Path, patch_path: Option<&Path>) -> Result<Cartridge, CartridgeError> {
+        let mut data = fs::read(path)?;
+
+        let patch_path = match patch_path {
+            Some(patch_path) => Some(patch_path.to_path_buf()),
+            None => patch::find_patch(path),
+        };
+
+        if let Some(patch_path) = &patch_path {
+            let patch_data = fs::read(patch_path).map_err(|err| CartridgeError::PatchIo(patch_path.clone(), err))?;
+            data = match patch::apply(data, &patch_data) {
+                Ok(data) => data,
+                Err(err) => return Err(CartridgeError::Patch(patch_path.clone(), err)),
+            };
+        }
+
+        let mut cartridge = Cartridge::from_bytes(data)?;
+        cartridge.patch = patch_path;
+        Ok(cartridge)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        if data.len() < cartridge_header::LENGTH {
+            return Err(CartridgeError::TooSmall(data.len()));
+        }
+
+        if data.len() > cartridge_header::MAX_ROM_SIZE {
+            return Err(CartridgeError::TooLarge(data.len()));
+        }
+
+        Header::parse(&data[..cartridge_header::LENGTH])?;
+        Ok(Cartridge::from_image(data))
+    }
+
+    // for roms straight out of the linker, which haven't had their header
+    // fixed up yet and so can't be held to the checksum
+    pub fn from_image(mut data: Vec<u8>) -> Cartridge {
+        if data.len() < cartridge_header::LENGTH {
+            data.resize(cartridge_header::LENGTH, 0);
+        }
+        data.truncate(cartridge_header::MAX_ROM_SIZE);
+
+        Cartridge {
+            header: Header::read(&data[..cartridge_header::LENGTH]),
+            rom: data.into_boxed_slice(),
+            backup: Backup::None,
+            gpio: None,
+            patch: None,
+            dma_request: false,
+        }
+    }
+
+    pub fn set_backup(&mut self, backup_type: BackupType) {
+        self.backup = Backup::new(backup_type);
+    }
+
+    // picks the save hardware from the user's choice, then the library
+    // strings in the rom, falling back to the game code table
+    pub fn detect_backup(&mut self, user: Option<BackupType>) -> (BackupType, DetectionSource) {
+        let (backup_type, source) = detect::detect(&self.rom, &self.header.game_code, user);
+        self.set_backup(backup_type);
+        (backup_type, source)
+    }
+
+    // hooks up the clock chip if the rom carries the rtc library or is a game
+    // known to have one, returning whether it did
+    pub fn detect_rtc(&mut self, clock: Clock) -> bool {
+        if !detect::has_rtc(&self.rom, &self.header.game_code) {
+            return false;
+        }
+
+        self.gpio = Some(Gpio::new(Some(Rtc::new(clock))));
+        true
+    }
+
+    // reports whether the save or the clock changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        let backup = self.backup.take_dirty();
+        let rtc = match self.gpio.as_mut().and_then(|gpio| gpio.rtc.as_mut()) {
+            Some(rtc) => std::mem::replace(&mut rtc.dirty, false),
+            None => false,
+        };
+
+        backup || rtc
+    }
+
+    // with more than 16 MiB of rom the eeprom only answers in the last 256
+    // bytes of the 0x0D00_0000 mirror, otherwise it takes the whole region
+    fn eeprom_addr(&self, addr: usize) -> bool {
+        let start = if self.rom.len() > 0x0100_0000 { memory_region::EEPROM_LARGE_ROM } else { memory_region::EEPROM };
+        matches!(self.backup, Backup::Eeprom(_)) && (start..=memory_region::ROM_END).contains(&addr)
+    }
+
+    // games talk to the eeprom with dma3, and the length of the first
+    // transfer is what gives away the size of an unknown chip
+    pub fn dma_transfer(&mut self, addr: usize, length: usize) {
+        let eeprom_addr = self.eeprom_addr(addr);
+        if let Backup::Eeprom(eeprom) = &mut self.backup {
+            if eeprom_addr {
+                eeprom.dma_transfer_length(length);
+            }
+        }
+    }
+
+    // the eeprom is one bit wide and hangs off the low bit of the halfword
+    // bus, so only the even byte of each access talks to it
+    pub fn rbyte(&self, addr: usize) -> u8 {
+        if let Some(gpio) = self.gpio.as_ref().filter(|gpio| gpio.readable && Gpio::contains(addr)) {
+            return gpio.rbyte(addr);
+        }
+
+        match (addr, &self.backup) {
+            (memory_region::SRAM..=memory_region::SRAM_END, backup) => backup.rbyte(addr),
+            (_, Backup::Eeprom(eeprom)) if self.eeprom_addr(addr) => {
+                if (addr & 1) == 0 { eeprom.read() as u8 } else { 0 }
+            },
+            _ => self.rom_byte(addr),
+        }
+    }
+
+    pub fn wbyte(&mut self, addr: usize, data: u8) {
+        let eeprom_addr = self.eeprom_addr(addr);
+
+        if let Some(gpio) = self.gpio.as_mut().filter(|_| Gpio::contains(addr)) {
+            gpio.wbyte(addr, data);
+            return;
+        }
+
+        match (addr, &mut self.backup) {
+            (memory_region::SRAM..=memory_region::SRAM_END, backup) => backup.wbyte(addr, data),
+            (_, Backup::Eeprom(eeprom)) if eeprom_addr && (addr & 1) == 0 => eeprom.write(data as u16),
+            _ => {},
+        }
+    }
+
+    // reads past the end of the rom return the low bits of the halfword
+    // address, which is what the cartridge bus is left holding
+    fn rom_byte(&self, addr: usize) -> u8 {
+        let offset = addr & cartridge_header::ROM_MASK;
+
+        if offset < self.rom.len() {
+            self.rom[offset]
+        } else {
+            let open_bus = ((offset >> 1) & 0xFFFF) as u16;
+            (open_bus >> ((offset & 1) * 8)) as u8
+        }
+    }
+}
+
+impl Header {
+    pub fn parse(data: &[u8]) -> Result<Header, CartridgeError> {
+        let expected = data[cartridge_header::COMPLEMENT_CHECK];
+        let computed = complement_check(data);
+
+        if expected != computed {
+            return Err(CartridgeError::ChecksumMismatch { expected, computed });
+        }
+
+        Ok(Header::read(data))
+    }
+
+    pub fn read(data: &[u8]) -> Header {
+        // a b instruction's offset is in words from two instructions ahead
+        let branch = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let offset = (((branch << 8) as i32) >> 6) as u32;
+
+        Header {
+            entry_offset: offset.wrapping_add(8),
+            title: ascii_field(&data[cartridge_header::TITLE..cartridge_header::GAME_CODE]),
+            game_code: ascii_field(&data[cartridge_header::GAME_CODE..cartridge_header::MAKER_CODE]),
+            maker_code: ascii_field(&data[cartridge_header::MAKER_CODE..cartridge_header::FIXED_VALUE]),
+            unit_code: data[cartridge_header::UNIT_CODE],
+            software_version: data[cartridge_header::SOFTWARE_VERSION],
+        }
+    }
+}
+
+// the bios sums 0xA0-0xBC and expects the negated sum minus 0x19 at 0xBD
+pub fn complement_check(data: &[u8]) -> u8 {
+    let mut check: u8 = 0;
+
+    for byte in &data[cartridge_header::TITLE..cartridge_header::COMPLEMENT_CHECK] {
+        check = check.wrapping_sub(*byte);
+    }
+
+    check.wrapping_sub(0x19)
+}
+
+fn ascii_field(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '?' })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+impl From<io::Error> for CartridgeError {
+    fn from(err: io::Error) -> CartridgeError {
+        CartridgeError::Io(err)
+    }
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Io(err) => write!(f, "could not read rom: {}", err),
+            CartridgeError::TooSmall(size) => {
+                write!(f, "rom is {} bytes, smaller than the {} byte header", size, cartridge_header::LENGTH)
+            },
+            CartridgeError::TooLarge(size) => {
+                write!(f, "rom is {} bytes, larger than the {} byte cartridge space", size, cartridge_header::MAX_ROM_SIZE)
+            },
+            CartridgeError::ChecksumMismatch { expected, computed } => {
+                write!(f, "header checksum mismatch: header says {:#04x}, computed {:#04x}", expected, computed)
+            },
+            CartridgeError::PatchIo(path, err) => write!(f, "could not read patch {}: {}", path.display(), err),
+            CartridgeError::Patch(path, err) => write!(f, "could not apply {}: {}", path.display(), err),
+        }
+    }
+}
+
+impl fmt::Debug for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
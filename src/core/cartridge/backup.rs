@@ -0,0 +1,461 @@
+use std::cell::Cell;
+use std::fmt;
+
+use crate::constants::backup_size;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BackupType {
+    None,
+    Sram,
+    // the library strings don't say who made the chip, so plain flash is
+    // taken to be macronix. the others are for games that check the id
+    Flash64K,
+    Flash64KPanasonic,
+    Flash64KAtmel,
+    Flash128K,
+    Flash128KSanyo,
+    Eeprom,
+    Eeprom512,
+    Eeprom8K,
+}
+
+impl BackupType {
+    pub fn parse(name: &str) -> Option<BackupType> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(BackupType::None),
+            "sram" => Some(BackupType::Sram),
+            "flash" | "flash64k" | "flash512" | "flash64k-macronix" => Some(BackupType::Flash64K),
+            "flash64k-panasonic" => Some(BackupType::Flash64KPanasonic),
+            "flash64k-atmel" => Some(BackupType::Flash64KAtmel),
+            "flash128k" | "flash1m" | "flash128k-macronix" => Some(BackupType::Flash128K),
+            "flash128k-sanyo" => Some(BackupType::Flash128KSanyo),
+            "eeprom" => Some(BackupType::Eeprom),
+            "eeprom512" => Some(BackupType::Eeprom512),
+            "eeprom8k" => Some(BackupType::Eeprom8K),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BackupType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BackupType::None => "none",
+            BackupType::Sram => "sram (32 KiB)",
+            BackupType::Flash64K => "flash (64 KiB, macronix)",
+            BackupType::Flash64KPanasonic => "flash (64 KiB, panasonic)",
+            BackupType::Flash64KAtmel => "flash (64 KiB, atmel)",
+            BackupType::Flash128K => "flash (128 KiB, macronix)",
+            BackupType::Flash128KSanyo => "flash (128 KiB, sanyo)",
+            BackupType::Eeprom => "eeprom (size from first access)",
+            BackupType::Eeprom512 => "eeprom (512 B)",
+            BackupType::Eeprom8K => "eeprom (8 KiB)",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub enum Backup {
+    None,
+    Sram(Sram),
+    Flash(Flash),
+    Eeprom(Eeprom),
+}
+
+impl Backup {
+    pub fn new(backup_type: BackupType) -> Backup {
+        match backup_type {
+            BackupType::None => Backup::None,
+            BackupType::Sram => Backup::Sram(Sram::new()),
+            BackupType::Flash64K => Backup::Flash(Flash::new(FlashChip::Macronix64K)),
+            BackupType::Flash64KPanasonic => Backup::Flash(Flash::new(FlashChip::Panasonic64K)),
+            BackupType::Flash64KAtmel => Backup::Flash(Flash::new(FlashChip::Atmel64K)),
+            BackupType::Flash128K => Backup::Flash(Flash::new(FlashChip::Macronix128K)),
+            BackupType::Flash128KSanyo => Backup::Flash(Flash::new(FlashChip::Sanyo128K)),
+            BackupType::Eeprom => Backup::Eeprom(Eeprom::new(EepromSize::Unknown)),
+            BackupType::Eeprom512 => Backup::Eeprom(Eeprom::new(EepromSize::Small)),
+            BackupType::Eeprom8K => Backup::Eeprom(Eeprom::new(EepromSize::Large)),
+        }
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        match self {
+            Backup::None => &[],
+            Backup::Sram(sram) => &sram.data,
+            Backup::Flash(flash) => &flash.data,
+            Backup::Eeprom(eeprom) => eeprom.contents(),
+        }
+    }
+
+    // a save file of the wrong length is still taken as far as it goes. an
+    // eeprom that hasn't been sized yet gets its size from the file
+    pub fn load_contents(&mut self, data: &[u8]) {
+        let target: &mut [u8] = match self {
+            Backup::None => return,
+            Backup::Sram(sram) => &mut sram.data,
+            Backup::Flash(flash) => &mut flash.data,
+            Backup::Eeprom(eeprom) => {
+                if eeprom.size == EepromSize::Unknown {
+                    match data.len() {
+                        backup_size::EEPROM_512 => eeprom.size = EepromSize::Small,
+                        backup_size::EEPROM_8K => eeprom.size = EepromSize::Large,
+                        _ => {},
+                    }
+                }
+                &mut eeprom.data
+            },
+        };
+
+        let length = data.len().min(target.len());
+        target[..length].copy_from_slice(&data[..length]);
+    }
+
+    // reports whether the game has changed the save since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        let dirty = match self {
+            Backup::None => return false,
+            Backup::Sram(sram) => &mut sram.dirty,
+            Backup::Flash(flash) => &mut flash.dirty,
+            Backup::Eeprom(eeprom) => &mut eeprom.dirty,
+        };
+
+        std::mem::replace(dirty, false)
+    }
+
+    // reads and writes in the 0x0E00_0000 region; the eeprom lives on the
+    // rom bus instead and is reached through its own functions
+    pub fn rbyte(&self, addr: usize) -> u8 {
+        match self {
+            Backup::Sram(sram) => sram.rbyte(addr),
+            Backup::Flash(flash) => flash.rbyte(addr),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn wbyte(&mut self, addr: usize, data: u8) {
+        match self {
+            Backup::Sram(sram) => sram.wbyte(addr, data),
+            Backup::Flash(flash) => flash.wbyte(addr, data),
+            _ => {},
+        }
+    }
+}
+
+pub struct Sram {
+    pub data: Box<[u8]>,
+    pub dirty: bool,
+}
+
+impl Sram {
+    pub fn new() -> Sram {
+        Sram {
+            data: vec![0xFFu8; backup_size::SRAM].into_boxed_slice(),
+            dirty: false,
+        }
+    }
+
+    pub fn rbyte(&self, addr: usize) -> u8 {
+        self.data[addr & (backup_size::SRAM - 1)]
+    }
+
+    pub fn wbyte(&mut self, addr: usize, data: u8) {
+        self.data[addr & (backup_size::SRAM - 1)] = data;
+        self.dirty = true;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FlashChip {
+    Macronix64K,
+    Panasonic64K,
+    Atmel64K,
+    Sanyo128K,
+    Macronix128K,
+}
+
+impl FlashChip {
+    // manufacturer in the low byte, device in the high byte, the order the
+    // two come back in from addresses 0 and 1 in id mode
+    pub fn id(&self) -> u16 {
+        match self {
+            FlashChip::Macronix64K => 0x1CC2,
+            FlashChip::Panasonic64K => 0x1B32,
+            FlashChip::Atmel64K => 0x3D1F,
+            FlashChip::Sanyo128K => 0x1362,
+            FlashChip::Macronix128K => 0x09C2,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            FlashChip::Sanyo128K | FlashChip::Macronix128K => backup_size::FLASH_128K,
+            _ => backup_size::FLASH_64K,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlashState {
+    Ready,
+    Command1,
+    Command2,
+    Write,
+    AtmelPage(usize),
+    BankSwitch,
+}
+
+mod flash_command {
+    pub const ENTER_ID: u8 = 0x90;
+    pub const EXIT_ID: u8 = 0xF0;
+    pub const ERASE: u8 = 0x80;
+    pub const ERASE_CHIP: u8 = 0x10;
+    pub const ERASE_SECTOR: u8 = 0x30;
+    pub const WRITE: u8 = 0xA0;
+    pub const BANK_SWITCH: u8 = 0xB0;
+}
+
+const FLASH_COMMAND_ADDR_1: usize = 0x5555;
+const FLASH_COMMAND_ADDR_2: usize = 0x2AAA;
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+const FLASH_BANK_SIZE: usize = 0x1_0000;
+const ATMEL_PAGE_SIZE: usize = 0x80;
+
+pub struct Flash {
+    pub data: Box<[u8]>,
+    pub chip: FlashChip,
+    pub dirty: bool,
+    state: FlashState,
+    id_mode: bool,
+    erase_armed: bool,
+    bank: usize,
+}
+
+impl Flash {
+    pub fn new(chip: FlashChip) -> Flash {
+        Flash {
+            data: vec![0xFFu8; chip.size()].into_boxed_slice(),
+            chip,
+            dirty: false,
+            state: FlashState::Ready,
+            id_mode: false,
+            erase_armed: false,
+            bank: 0,
+        }
+    }
+
+    pub fn rbyte(&self, addr: usize) -> u8 {
+        let addr = addr & (FLASH_BANK_SIZE - 1);
+
+        if self.id_mode && addr < 2 {
+            return (self.chip.id() >> (addr * 8)) as u8;
+        }
+
+        self.data[self.bank * FLASH_BANK_SIZE + addr]
+    }
+
+    // every command starts with 0xAA to 0x5555 then 0x55 to 0x2AAA, with the
+    // command byte itself written to 0x5555
+    pub fn wbyte(&mut self, addr: usize, data: u8) {
+        let addr = addr & (FLASH_BANK_SIZE - 1);
+
+        match self.state {
+            FlashState::Ready => {
+                if addr == FLASH_COMMAND_ADDR_1 && data == 0xAA {
+                    self.state = FlashState::Command1;
+                }
+            },
+
+            FlashState::Command1 => {
+                self.state = if addr == FLASH_COMMAND_ADDR_2 && data == 0x55 { FlashState::Command2 } else { FlashState::Ready };
+            },
+
+            FlashState::Command2 => {
+                self.state = FlashState::Ready;
+                self.command(addr, data);
+            },
+
+            FlashState::Write => {
+                self.data[self.bank * FLASH_BANK_SIZE + addr] = data;
+                self.dirty = true;
+                self.state = FlashState::Ready;
+            },
+
+            FlashState::AtmelPage(remaining) => {
+                if remaining == ATMEL_PAGE_SIZE {
+                    let start = self.bank * FLASH_BANK_SIZE + (addr & !(ATMEL_PAGE_SIZE - 1));
+                    self.data[start..start + ATMEL_PAGE_SIZE].fill(0xFF);
+                }
+                self.data[self.bank * FLASH_BANK_SIZE + addr] = data;
+                self.dirty = true;
+                self.state = if remaining > 1 { FlashState::AtmelPage(remaining - 1) } else { FlashState::Ready };
+            },
+
+            FlashState::BankSwitch => {
+                if addr == 0 {
+                    self.bank = (data & 1) as usize;
+                }
+                self.state = FlashState::Ready;
+            },
+        }
+    }
+
+    fn command(&mut self, addr: usize, data: u8) {
+        if self.erase_armed {
+            self.erase_armed = false;
+            self.dirty = true;
+
+            if addr == FLASH_COMMAND_ADDR_1 && data == flash_command::ERASE_CHIP {
+                self.data.fill(0xFF);
+            } else if data == flash_command::ERASE_SECTOR {
+                let start = self.bank * FLASH_BANK_SIZE + (addr & !(FLASH_SECTOR_SIZE - 1));
+                self.data[start..start + FLASH_SECTOR_SIZE].fill(0xFF);
+            }
+            return;
+        }
+
+        if addr != FLASH_COMMAND_ADDR_1 {
+            return;
+        }
+
+        match data {
+            flash_command::ENTER_ID => self.id_mode = true,
+            flash_command::EXIT_ID => self.id_mode = false,
+            flash_command::ERASE => self.erase_armed = true,
+            flash_command::WRITE => {
+                // atmel chips have no sector erase and program a whole 128
+                // byte page at a time, erasing it first
+                if self.chip == FlashChip::Atmel64K {
+                    self.state = FlashState::AtmelPage(ATMEL_PAGE_SIZE);
+                } else {
+                    self.state = FlashState::Write;
+                }
+            },
+            flash_command::BANK_SWITCH if self.chip.size() == backup_size::FLASH_128K => {
+                self.state = FlashState::BankSwitch;
+            },
+            _ => {},
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EepromSize {
+    Unknown,
+    Small,
+    Large,
+}
+
+impl EepromSize {
+    fn address_bits(&self) -> usize {
+        match self {
+            EepromSize::Large => 14,
+            _ => 6,
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        match self {
+            EepromSize::Large => backup_size::EEPROM_8K,
+            _ => backup_size::EEPROM_512,
+        }
+    }
+}
+
+const EEPROM_BLOCK_SIZE: usize = 8;
+const EEPROM_READ_BITS: u32 = 68;
+
+pub struct Eeprom {
+    pub data: Box<[u8]>,
+    pub size: EepromSize,
+    pub dirty: bool,
+    request: Vec<u8>,
+    read_addr: usize,
+    read_bit: Cell<u32>,
+}
+
+impl Eeprom {
+    pub fn new(size: EepromSize) -> Eeprom {
+        Eeprom {
+            data: vec![0xFFu8; backup_size::EEPROM_8K].into_boxed_slice(),
+            size,
+            dirty: false,
+            request: Vec::with_capacity(81),
+            read_addr: 0,
+            read_bit: Cell::new(EEPROM_READ_BITS),
+        }
+    }
+
+    // the bytes actually backed by the chip, for writing out to a save file
+    pub fn contents(&self) -> &[u8] {
+        &self.data[..self.size.bytes()]
+    }
+
+    // the only place the address width shows up is in how many halfwords
+    // the game has dma3 move: 9 or 73 for a 6-bit address, 17 or 81 for 14
+    pub fn dma_transfer_length(&mut self, length: usize) {
+        if self.size != EepromSize::Unknown {
+            return;
+        }
+
+        match length {
+            9 | 73 => self.size = EepromSize::Small,
+            17 | 81 => self.size = EepromSize::Large,
+            _ => {},
+        }
+    }
+
+    // four junk bits then 64 data bits msb first; outside of a read the
+    // chip holds the line high to say it's ready
+    pub fn read(&self) -> u16 {
+        let bit = self.read_bit.get();
+
+        if bit >= EEPROM_READ_BITS {
+            return 1;
+        }
+
+        self.read_bit.set(bit + 1);
+
+        if bit < 4 {
+            return 0;
+        }
+
+        let data_bit = (bit - 4) as usize;
+        let byte = self.data[self.read_addr + data_bit / 8];
+        ((byte >> (7 - (data_bit % 8))) & 1) as u16
+    }
+
+    pub fn write(&mut self, data: u16) {
+        self.request.push((data & 1) as u8);
+
+        let address_bits = self.size.address_bits();
+        let read_length = 2 + address_bits + 1;
+        let write_length = 2 + address_bits + 64 + 1;
+
+        match (self.request[0], self.request.get(1)) {
+            (1, Some(1)) if self.request.len() == read_length => {
+                self.read_addr = self.block_addr(address_bits);
+                self.read_bit.set(0);
+                self.request.clear();
+            },
+
+            (1, Some(0)) if self.request.len() == write_length => {
+                let addr = self.block_addr(address_bits);
+
+                for i in 0..EEPROM_BLOCK_SIZE {
+                    let bits = &self.request[2 + address_bits + i * 8..2 + address_bits + (i + 1) * 8];
+                    self.data[addr + i] = bits.iter().fold(0, |byte, bit| (byte << 1) | bit);
+                }
+                self.dirty = true;
+                self.request.clear();
+            },
+
+            (0, _) => self.request.clear(),
+            _ => {},
+        }
+    }
+
+    fn block_addr(&self, address_bits: usize) -> usize {
+        let block = self.request[2..2 + address_bits].iter().fold(0usize, |addr, &bit| (addr << 1) | bit as usize);
+        (block * EEPROM_BLOCK_SIZE) & (backup_size::EEPROM_8K - 1)
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+
+use crate::core::cartridge::backup::BackupType;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DetectionSource {
+    User,
+    LibraryString,
+    GameDatabase,
+    Default,
+}
+
+// the save libraries nintendo shipped to developers embed their version
+// string in the rom, always word aligned. longer names go first so
+// FLASH512_V and FLASH1M_V aren't taken for FLASH_V
+const LIBRARY_STRINGS: [(&[u8], BackupType); 6] = [
+    (b"FLASH1M_V", BackupType::Flash128K),
+    (b"FLASH512_V", BackupType::Flash64K),
+    (b"FLASH_V", BackupType::Flash64K),
+    (b"SRAM_F_V", BackupType::Sram),
+    (b"SRAM_V", BackupType::Sram),
+    (b"EEPROM_V", BackupType::Eeprom),
+];
+
+// the fallback for games where no library string turns up in the rom
+const GAME_DATABASE: [(&str, BackupType); 14] = [
+    ("AWRE", BackupType::Flash64K), // advance wars
+    ("AWRP", BackupType::Flash64K),
+    ("AXVE", BackupType::Flash128K), // pokemon ruby
+    ("AXPE", BackupType::Flash128K), // pokemon sapphire
+    ("BPEE", BackupType::Flash128K), // pokemon emerald
+    ("BPRE", BackupType::Flash128K), // pokemon firered
+    ("BPGE", BackupType::Flash128K), // pokemon leafgreen
+    ("U3IE", BackupType::Eeprom8K), // boktai
+    ("U3IP", BackupType::Eeprom8K),
+    ("U32E", BackupType::Eeprom8K), // boktai 2
+    ("ALFE", BackupType::Eeprom8K), // dragon ball z: the legacy of goku ii
+    ("ALFP", BackupType::Eeprom8K),
+    ("AYGE", BackupType::Eeprom8K), // gauntlet: dark legacy
+    ("A2YE", BackupType::None), // top gun: combat zones
+];
+
+// games that read the clock through the gpio port, for roms that don't
+// carry the SIIRTC_V library string
+const RTC_GAMES: [&str; 7] = ["AXVE", "AXPE", "BPEE", "U3IE", "U3IP", "U32E", "U32P"];
+
+pub fn has_rtc(rom: &[u8], game_code: &str) -> bool {
+    RTC_GAMES.contains(&game_code) || scan(rom, b"SIIRTC_V")
+}
+
+pub fn detect(rom: &[u8], game_code: &str, user: Option<BackupType>) -> (BackupType, DetectionSource) {
+    if let Some(backup_type) = user {
+        return (backup_type, DetectionSource::User);
+    }
+
+    if let Some(backup_type) = scan_library_strings(rom) {
+        return (backup_type, DetectionSource::LibraryString);
+    }
+
+    for (code, backup_type) in GAME_DATABASE {
+        if code == game_code {
+            return (backup_type, DetectionSource::GameDatabase);
+        }
+    }
+
+    (BackupType::None, DetectionSource::Default)
+}
+
+fn scan_library_strings(rom: &[u8]) -> Option<BackupType> {
+    for offset in (0..rom.len()).step_by(4) {
+        for (name, backup_type) in LIBRARY_STRINGS {
+            if rom[offset..].starts_with(name) {
+                return Some(backup_type);
+            }
+        }
+    }
+
+    None
+}
+
+fn scan(rom: &[u8], name: &[u8]) -> bool {
+    (0..rom.len()).step_by(4).any(|offset| rom[offset..].starts_with(name))
+}
+
+impl fmt::Display for DetectionSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DetectionSource::User => "set by user",
+            DetectionSource::LibraryString => "found library string in rom",
+            DetectionSource::GameDatabase => "from game code override table",
+            DetectionSource::Default => "no save hardware found",
+        };
+        write!(f, "{}", name)
+    }
+}
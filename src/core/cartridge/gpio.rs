@@ -0,0 +1,60 @@
+use crate::core::cartridge::gpio::rtc::Rtc;
+use crate::constants::gpio_address;
+
+pub mod rtc;
+
+// the four pin port some cartridges wire up in the rom space. the data
+// register reads back the rom underneath unless the control register has
+// been set to make the port readable
+pub struct Gpio {
+    pub data: u8,
+    pub direction: u8,
+    pub readable: bool,
+    pub rtc: Option<Rtc>,
+}
+
+impl Gpio {
+    pub fn new(rtc: Option<Rtc>) -> Gpio {
+        Gpio {
+            data: 0,
+            direction: 0,
+            readable: false,
+            rtc,
+        }
+    }
+
+    pub fn contains(addr: usize) -> bool {
+        (gpio_address::DATA..gpio_address::CONTROL + 2).contains(&addr)
+    }
+
+    // pins set as outputs read back what the game wrote, inputs read what
+    // the device on the other end is driving
+    pub fn rbyte(&self, addr: usize) -> u8 {
+        match addr {
+            gpio_address::DATA => {
+                let device = match &self.rtc {
+                    Some(rtc) => rtc.output(),
+                    None => 0,
+                };
+                ((self.data & self.direction) | (device & !self.direction)) & 0xF
+            },
+            gpio_address::DIRECTION => self.direction,
+            gpio_address::CONTROL => self.readable as u8,
+            _ => 0,
+        }
+    }
+
+    pub fn wbyte(&mut self, addr: usize, data: u8) {
+        match addr {
+            gpio_address::DATA => {
+                self.data = data & 0xF;
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_pins((self.data & self.direction) | (rtc.output() & !self.direction));
+                }
+            },
+            gpio_address::DIRECTION => self.direction = data & 0xF,
+            gpio_address::CONTROL => self.readable = (data & 1) != 0,
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// pins as wired on the cartridge
+const SCK: u8 = 1 << 0;
+const SIO: u8 = 1 << 1;
+const CS: u8 = 1 << 2;
+
+mod command {
+    pub const RESET: u8 = 0;
+    pub const DATE_TIME: u8 = 2;
+    pub const ALARM: u8 = 3;
+    pub const STATUS: u8 = 4;
+    pub const TIME: u8 = 6;
+}
+
+// status register bit that selects a 24 hour clock over am/pm
+const STATUS_24_HOUR: u8 = 1 << 6;
+// set on power up until the game clears it, how games spot a dead battery
+const STATUS_POWER_LOST: u8 = 1 << 7;
+
+pub const STATE_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Clock {
+    Host,
+    Offset(i64),
+    Fixed(i64),
+}
+
+impl Clock {
+    fn now(&self) -> i64 {
+        let host = || SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs() as i64).unwrap_or(0);
+
+        match self {
+            Clock::Host => host(),
+            Clock::Offset(offset) => host() + offset,
+            Clock::Fixed(time) => *time,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TransferState {
+    Idle,
+    Selecting,
+    Transfer,
+}
+
+pub struct Rtc {
+    pub clock: Clock,
+    pub status: u8,
+    pub alarm: [u8; 2],
+    pub dirty: bool,
+    // seconds between what the game set the clock to and the clock source
+    adjustment: i64,
+    state: TransferState,
+    pins: u8,
+    command: Option<u8>,
+    bits: u8,
+    bit_count: u32,
+    buffer: [u8; 7],
+    byte_index: usize,
+    byte_count: usize,
+}
+
+impl Rtc {
+    pub fn new(clock: Clock) -> Rtc {
+        Rtc {
+            clock,
+            status: STATUS_POWER_LOST | STATUS_24_HOUR,
+            alarm: [0; 2],
+            dirty: false,
+            adjustment: 0,
+            state: TransferState::Idle,
+            pins: 0,
+            command: None,
+            bits: 0,
+            bit_count: 0,
+            buffer: [0; 7],
+            byte_index: 0,
+            byte_count: 0,
+        }
+    }
+
+    // the pin the chip is driving, only meaningful while a read is going
+    pub fn output(&self) -> u8 {
+        self.pins & SIO
+    }
+
+    // serial transfers are framed by cs going high with sck high, then one
+    // bit per sck rising edge, lsb first. the first byte is the command
+    pub fn write_pins(&mut self, pins: u8) {
+        let rising = (self.pins & SCK) == 0 && (pins & SCK) != 0;
+        let input = pins & SIO;
+
+        match self.state {
+            TransferState::Idle => {
+                if (pins & (CS | SCK)) == SCK {
+                    self.state = TransferState::Selecting;
+                }
+                self.pins = pins;
+            },
+
+            TransferState::Selecting => {
+                if (pins & (CS | SCK)) == (CS | SCK) {
+                    self.state = TransferState::Transfer;
+                    self.command = None;
+                    self.bits = 0;
+                    self.bit_count = 0;
+                } else if (pins & SCK) == 0 {
+                    self.state = TransferState::Idle;
+                }
+                self.pins = pins;
+            },
+
+            TransferState::Transfer => {
+                if (pins & CS) == 0 {
+                    self.state = TransferState::Idle;
+                    self.pins = pins;
+                    return;
+                }
+
+                let reading = matches!(self.command, Some(command) if (command & 0x80) != 0);
+                self.pins = if reading { (pins & !SIO) | (self.pins & SIO) } else { pins };
+
+                if !rising {
+                    return;
+                }
+
+                if reading {
+                    self.pins = (self.pins & !SIO) | (self.read_bit() << 1);
+                    self.advance_read();
+                } else {
+                    self.bits |= (input >> 1) << self.bit_count;
+                    self.bit_count += 1;
+
+                    if self.bit_count == 8 {
+                        let byte = self.bits;
+                        self.bits = 0;
+                        self.bit_count = 0;
+                        self.process_byte(byte);
+                    }
+                }
+            },
+        }
+    }
+
+    fn read_bit(&self) -> u8 {
+        if self.byte_index >= self.byte_count {
+            return 0;
+        }
+        (self.buffer[self.byte_index] >> self.bit_count) & 1
+    }
+
+    fn advance_read(&mut self) {
+        self.bit_count += 1;
+
+        if self.bit_count == 8 {
+            self.bit_count = 0;
+            self.byte_index += 1;
+
+            if self.byte_index >= self.byte_count {
+                self.command = None;
+            }
+        }
+    }
+
+    fn process_byte(&mut self, byte: u8) {
+        let command = match self.command {
+            Some(command) => command,
+            None => {
+                // low nibble is always 0110, bits 4-6 pick the register and
+                // bit 7 is set for reads
+                if (byte & 0xF) != 0x6 {
+                    return;
+                }
+                self.start_command(byte);
+                return;
+            },
+        };
+
+        if self.byte_index < self.byte_count {
+            self.buffer[self.byte_index] = byte;
+            self.byte_index += 1;
+        }
+
+        if self.byte_index == self.byte_count {
+            self.finish_write((command >> 4) & 0x7);
+        }
+    }
+
+    fn start_command(&mut self, byte: u8) {
+        let register = (byte >> 4) & 0x7;
+        let reading = (byte & 0x80) != 0;
+
+        self.command = Some(byte);
+        self.byte_index = 0;
+        self.byte_count = match register {
+            command::DATE_TIME => 7,
+            command::ALARM => 2,
+            command::STATUS => 1,
+            command::TIME => 3,
+            _ => 0,
+        };
+
+        if register == command::RESET {
+            self.status = 0;
+            self.adjustment = 0;
+            self.dirty = true;
+            self.command = None;
+            return;
+        }
+
+        if reading {
+            let date_time = self.date_time();
+            match register {
+                command::DATE_TIME => self.buffer = date_time,
+                command::TIME => self.buffer[..3].copy_from_slice(&date_time[4..]),
+                command::STATUS => self.buffer[0] = self.status,
+                command::ALARM => self.buffer[..2].copy_from_slice(&self.alarm),
+                _ => {},
+            }
+        }
+    }
+
+    fn finish_write(&mut self, register: u8) {
+        match register {
+            command::STATUS => {
+                // the power flag is read only, games clear it by resetting
+                self.status = (self.buffer[0] & !STATUS_POWER_LOST) | (self.status & STATUS_POWER_LOST);
+            },
+            command::ALARM => self.alarm.copy_from_slice(&self.buffer[..2]),
+            command::DATE_TIME => {
+                let time = from_date_time(&self.buffer);
+                self.adjustment = time - self.clock.now();
+            },
+            command::TIME => {
+                let mut date_time = self.date_time();
+                date_time[4..].copy_from_slice(&self.buffer[..3]);
+                self.adjustment = from_date_time(&date_time) - self.clock.now();
+            },
+            _ => {},
+        }
+
+        self.dirty = true;
+        self.command = None;
+    }
+
+    // year, month, day, weekday, hour, minute, second, all in bcd
+    fn date_time(&self) -> [u8; 7] {
+        let time = self.clock.now() + self.adjustment;
+        let days = time.div_euclid(86400);
+        let seconds = time.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        let weekday = (days + 4).rem_euclid(7);
+        let hour = seconds / 3600;
+
+        let hour_field = if (self.status & STATUS_24_HOUR) != 0 { hour } else { hour % 12 };
+        let pm = if hour >= 12 { 0x80 } else { 0 };
+
+        [
+            bcd(year.rem_euclid(100)),
+            bcd(month),
+            bcd(day),
+            bcd(weekday),
+            bcd(hour_field) | pm,
+            bcd((seconds / 60) % 60),
+            bcd(seconds % 60),
+        ]
+    }
+
+    // kept after the backup data in the save file the way mgba lays it out:
+    // the date and time registers, status, then the clock source's unix time
+    // when they were read as a little endian u64. the adjustment comes back
+    // from the gap between the two
+    pub fn save_state(&self) -> [u8; STATE_LENGTH] {
+        let mut state = [0u8; STATE_LENGTH];
+        state[..7].copy_from_slice(&self.date_time());
+        state[7] = self.status;
+        state[8..16].copy_from_slice(&(self.clock.now() as u64).to_le_bytes());
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) {
+        if state.len() < STATE_LENGTH {
+            return;
+        }
+
+        let mut date_time = [0u8; 7];
+        date_time.copy_from_slice(&state[..7]);
+        self.status = state[7];
+
+        let mut latched = [0u8; 8];
+        latched.copy_from_slice(&state[8..16]);
+        self.adjustment = from_date_time(&date_time) - u64::from_le_bytes(latched) as i64;
+    }
+}
+
+fn bcd(value: i64) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+fn from_bcd(value: u8) -> i64 {
+    ((value >> 4) * 10 + (value & 0xF)) as i64
+}
+
+fn from_date_time(date_time: &[u8; 7]) -> i64 {
+    let year = 2000 + from_bcd(date_time[0]);
+    let month = from_bcd(date_time[1]);
+    let day = from_bcd(date_time[2]);
+    // the pm flag is set in either mode, but only adds to a 12 hour clock
+    let hour = match from_bcd(date_time[4] & 0x3F) {
+        hour if hour < 12 && (date_time[4] & 0x80) != 0 => hour + 12,
+        hour => hour,
+    };
+    let minute = from_bcd(date_time[5]);
+    let second = from_bcd(date_time[6]);
+
+    days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second
+}
+
+// proleptic gregorian conversions, see howard hinnant's date algorithms
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
@@ -0,0 +1,445 @@
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::checksum::crc32;
+use crate::constants::cartridge_header;
+
+pub enum PatchError {
+    UnknownFormat,
+    Truncated,
+    OutOfRange(usize),
+    SourceChecksum { expected: u32, actual: u32 },
+    TargetChecksum { expected: u32, actual: u32 },
+    PatchChecksum { expected: u32, actual: u32 },
+    SourceSize { expected: usize, actual: usize },
+    TargetTooLarge(usize),
+}
+
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+// looks for a patch with the rom's name next to it, e.g. game.bps for
+// game.gba, the way soft-patching emulators have always done it
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS.iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+// patches the rom in memory only; the file on disk is never touched
+pub fn apply(rom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], offset: usize) -> Reader<'a> {
+        Reader { data, offset }
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        let byte = *self.data.get(self.offset).ok_or(PatchError::Truncated)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.offset.checked_add(length).ok_or(PatchError::Truncated)?;
+        let bytes = self.data.get(self.offset..end).ok_or(PatchError::Truncated)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn big_endian(&mut self, length: usize) -> Result<usize, PatchError> {
+        let bytes = self.bytes(length)?;
+        Ok(bytes.iter().fold(0, |value, byte| (value << 8) | *byte as usize))
+    }
+
+    // ups and bps share this encoding: seven bits at a time, low first, with
+    // the top bit marking the last byte and an implied +1 on each carry.
+    // anything too big for a usize can only come from a corrupt patch
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.byte()?;
+            value = ((byte & 0x7F) as usize).checked_mul(shift)
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or(PatchError::Truncated)?;
+            if (byte & 0x80) != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::Truncated)?;
+            value = value.checked_add(shift).ok_or(PatchError::Truncated)?;
+        }
+    }
+}
+
+fn apply_ips(mut rom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = Reader::new(patch, 5);
+
+    loop {
+        if reader.data.get(reader.offset..reader.offset + 3) == Some(b"EOF") {
+            reader.offset += 3;
+            break;
+        }
+
+        let offset = reader.big_endian(3)?;
+        let length = reader.big_endian(2)?;
+
+        // a zero length record is a run of one repeated byte
+        let (length, run) = if length == 0 {
+            (reader.big_endian(2)?, Some(reader.byte()?))
+        } else {
+            (length, None)
+        };
+
+        if rom.len() < offset + length {
+            rom.resize(offset + length, 0);
+        }
+
+        match run {
+            Some(byte) => rom[offset..offset + length].fill(byte),
+            None => rom[offset..offset + length].copy_from_slice(reader.bytes(length)?),
+        }
+    }
+
+    // some patchers follow EOF with the size to cut the output down to
+    if let Ok(size) = reader.big_endian(3) {
+        rom.truncate(size);
+    }
+
+    Ok(rom)
+}
+
+// the target's checksum, kept for after patching once the patch and source
+// have checked out
+struct Footer {
+    target: u32,
+}
+
+fn check_footer(source: &[u8], patch: &[u8]) -> Result<Footer, PatchError> {
+    if patch.len() < 12 {
+        return Err(PatchError::Truncated);
+    }
+
+    let word = |offset: usize| u32::from_le_bytes([patch[offset], patch[offset + 1], patch[offset + 2], patch[offset + 3]]);
+    let footer = patch.len() - 12;
+    let expected_patch = word(footer + 8);
+    let actual_patch = crc32(&patch[..footer + 8]);
+
+    if expected_patch != actual_patch {
+        return Err(PatchError::PatchChecksum { expected: expected_patch, actual: actual_patch });
+    }
+
+    let expected_source = word(footer);
+    let actual_source = crc32(source);
+
+    if expected_source != actual_source {
+        return Err(PatchError::SourceChecksum { expected: expected_source, actual: actual_source });
+    }
+
+    Ok(Footer { target: word(footer + 4) })
+}
+
+// the sizes are read straight from the patch, so they're held to what
+// fits in the cartridge space before anything is allocated for them
+fn check_target_size(size: usize) -> Result<usize, PatchError> {
+    if size > cartridge_header::MAX_ROM_SIZE {
+        return Err(PatchError::TargetTooLarge(size));
+    }
+
+    Ok(size)
+}
+
+fn check_target(footer: &Footer, target: &[u8]) -> Result<(), PatchError> {
+    let actual = crc32(target);
+
+    if footer.target != actual {
+        return Err(PatchError::TargetChecksum { expected: footer.target, actual });
+    }
+
+    Ok(())
+}
+
+fn apply_ups(rom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let footer = check_footer(&rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader::new(patch, 4);
+
+    let source_size = reader.varint()?;
+    let target_size = check_target_size(reader.varint()?)?;
+
+    if source_size != rom.len() {
+        return Err(PatchError::SourceSize { expected: source_size, actual: rom.len() });
+    }
+
+    let mut target = rom.clone();
+    target.resize(target_size, 0);
+    let mut offset: usize = 0;
+
+    // each hunk skips ahead then xors bytes in until a zero byte
+    while reader.offset < end {
+        offset = offset.checked_add(reader.varint()?).ok_or(PatchError::OutOfRange(offset))?;
+
+        loop {
+            let byte = reader.byte()?;
+            if byte == 0 {
+                break;
+            }
+            if offset >= target_size {
+                return Err(PatchError::OutOfRange(offset));
+            }
+            target[offset] = rom.get(offset).copied().unwrap_or(0) ^ byte;
+            offset += 1;
+        }
+        offset += 1;
+    }
+
+    check_target(&footer, &target)?;
+    Ok(target)
+}
+
+mod bps_action {
+    pub const SOURCE_READ: usize = 0;
+    pub const TARGET_READ: usize = 1;
+    pub const SOURCE_COPY: usize = 2;
+    pub const TARGET_COPY: usize = 3;
+}
+
+fn apply_bps(rom: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let footer = check_footer(&rom, patch)?;
+    let end = patch.len() - 12;
+    let mut reader = Reader::new(patch, 4);
+
+    let source_size = reader.varint()?;
+    let target_size = check_target_size(reader.varint()?)?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != rom.len() {
+        return Err(PatchError::SourceSize { expected: source_size, actual: rom.len() });
+    }
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+
+    while reader.offset < end {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+
+        match data & 3 {
+            bps_action::SOURCE_READ => {
+                let start = target.len();
+                let bytes = rom.get(start..start + length).ok_or(PatchError::OutOfRange(start))?;
+                target.extend_from_slice(bytes);
+            },
+
+            bps_action::TARGET_READ => target.extend_from_slice(reader.bytes(length)?),
+
+            // copies move a cursor by a signed relative offset, sign in bit 0
+            bps_action::SOURCE_COPY => {
+                source_offset = relative_offset(source_offset, reader.varint()?)?;
+                let start = usize::try_from(source_offset).map_err(|_| PatchError::OutOfRange(0))?;
+                let bytes = rom.get(start..start + length).ok_or(PatchError::OutOfRange(start))?;
+                target.extend_from_slice(bytes);
+                source_offset += length as isize;
+            },
+
+            // may overlap what it is writing, so has to go a byte at a time
+            bps_action::TARGET_COPY => {
+                target_offset = relative_offset(target_offset, reader.varint()?)?;
+                for _ in 0..length {
+                    let start = usize::try_from(target_offset).map_err(|_| PatchError::OutOfRange(0))?;
+                    let byte = *target.get(start).ok_or(PatchError::OutOfRange(start))?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            },
+
+            _ => unreachable!(),
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(PatchError::OutOfRange(target.len()));
+    }
+
+    check_target(&footer, &target)?;
+    Ok(target)
+}
+
+fn relative_offset(offset: isize, data: usize) -> Result<isize, PatchError> {
+    let magnitude = (data >> 1) as isize;
+    let moved = if (data & 1) != 0 { offset.checked_sub(magnitude) } else { offset.checked_add(magnitude) };
+    moved.ok_or(PatchError::OutOfRange(0))
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an ips, ups or bps patch"),
+            PatchError::Truncated => write!(f, "patch ends part way through a record"),
+            PatchError::OutOfRange(offset) => write!(f, "patch refers to offset {:#x}, outside the rom", offset),
+            PatchError::SourceChecksum { expected, actual } => {
+                write!(f, "patch is for a different rom: expected crc32 {:08x}, rom has {:08x}", expected, actual)
+            },
+            PatchError::TargetChecksum { expected, actual } => {
+                write!(f, "patched rom has crc32 {:08x}, patch expected {:08x}", actual, expected)
+            },
+            PatchError::PatchChecksum { expected, actual } => {
+                write!(f, "patch file is corrupt: crc32 {:08x}, expected {:08x}", actual, expected)
+            },
+            PatchError::SourceSize { expected, actual } => {
+                write!(f, "patch is for a {} byte rom, this one is {} bytes", expected, actual)
+            },
+            PatchError::TargetTooLarge(size) => {
+                write!(f, "patch makes a {} byte rom, larger than the {} byte cartridge space", size, cartridge_header::MAX_ROM_SIZE)
+            },
+        }
+    }
+}
+
+impl fmt::Debug for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        loop {
+            let bits = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(bits | 0x80);
+                return bytes;
+            }
+            bytes.push(bits);
+            value -= 1;
+        }
+    }
+
+    // ups and bps end with the source, target and patch checksums
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    fn bps_header(source: &[u8], target_size: usize, metadata_size: usize) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target_size));
+        patch.extend(varint(metadata_size));
+        patch
+    }
+
+    #[test]
+    fn ips_writes_records_and_runs() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+
+        let rom = apply(vec![0; 8], &patch).unwrap();
+        assert_eq!(rom, [0x00, 0xAA, 0xBB, 0x00, 0x00, 0x00, 0xCC, 0xCC, 0xCC]);
+    }
+
+    #[test]
+    fn ips_rejects_a_cut_off_record() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x04, 0xAA]);
+
+        assert!(matches!(apply(vec![0; 8], &patch), Err(PatchError::Truncated)));
+    }
+
+    #[test]
+    fn ups_round_trips() {
+        let source = [1, 2, 3, 4, 5, 6];
+        let target = [1, 9, 3, 4, 5, 6, 7];
+
+        // skip one byte, xor in two, which ends the hunk past the second
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(1));
+        patch.extend_from_slice(&[2 ^ 9, 0]);
+        patch.extend(varint(3));
+        patch.extend_from_slice(&[7, 0]);
+        let patch = with_footer(patch, &source, &target);
+
+        assert_eq!(apply(source.to_vec(), &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn ups_rejects_a_corrupt_patch() {
+        let source = [1, 2, 3, 4];
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(4));
+        patch.extend(varint(4));
+        let mut patch = with_footer(patch, &source, &source);
+        patch[5] ^= 1;
+
+        assert!(matches!(apply(source.to_vec(), &patch), Err(PatchError::PatchChecksum { .. })));
+    }
+
+    #[test]
+    fn bps_round_trips() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 2, 8, 8, 8, 8, 3, 4];
+
+        // source read 2, target read 1, target copy 3 from that byte, source
+        // copy 2 from offset 2
+        let mut patch = bps_header(&source, target.len(), 0);
+        patch.extend(varint(((2 - 1) << 2) | bps_action::SOURCE_READ));
+        patch.extend(varint(bps_action::TARGET_READ));
+        patch.push(8);
+        patch.extend(varint(((3 - 1) << 2) | bps_action::TARGET_COPY));
+        patch.extend(varint(2 << 1));
+        patch.extend(varint(((2 - 1) << 2) | bps_action::SOURCE_COPY));
+        patch.extend(varint(2 << 1));
+        let patch = with_footer(patch, &source, &target);
+
+        assert_eq!(apply(source.to_vec(), &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_rejects_oversized_fields() {
+        let source = [1, 2, 3, 4];
+
+        let patch = with_footer(bps_header(&source, 4, usize::MAX - 2), &source, &source);
+        assert!(matches!(apply(source.to_vec(), &patch), Err(PatchError::Truncated)));
+
+        let patch = with_footer(bps_header(&source, usize::MAX, 0), &source, &source);
+        assert!(matches!(apply(source.to_vec(), &patch), Err(PatchError::TargetTooLarge(_))));
+    }
+
+    #[test]
+    fn bps_rejects_the_wrong_rom() {
+        let source = [1, 2, 3, 4];
+        let patch = with_footer(bps_header(&source, 4, 0), &source, &source);
+
+        assert!(matches!(apply(vec![1, 2, 3, 5], &patch), Err(PatchError::SourceChecksum { .. })));
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::core::cartridge::Cartridge;
+use crate::core::cartridge::gpio::rtc;
+
+// how long the game has to leave the save alone before it goes to disk.
+// games write a save in many small pieces and we only want the finished one
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub struct SaveFile {
+    pub path: PathBuf,
+    last_write: Option<Instant>,
+}
+
+impl SaveFile {
+    // the save sits next to the rom with a .sav extension unless a separate
+    // directory for saves was given
+    pub fn new(rom_path: &Path, save_dir: Option<&Path>) -> SaveFile {
+        let file_name = rom_path.with_extension("sav");
+        let path = match (save_dir, file_name.file_name()) {
+            (Some(dir), Some(name)) => dir.join(name),
+            _ => file_name,
+        };
+
+        SaveFile { path, last_write: None }
+    }
+
+    // a missing file just means the game hasn't been saved yet. saves are
+    // always a multiple of 512 bytes, so anything 16 over is the rtc state
+    // tacked onto the end, as mgba writes it
+    pub fn load(&self, cartridge: &mut Cartridge) -> io::Result<()> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                let rtc = cartridge.gpio.as_mut().and_then(|gpio| gpio.rtc.as_mut());
+                let backup_length = match rtc {
+                    Some(rtc) if data.len() % 512 == rtc::STATE_LENGTH => {
+                        let backup_length = data.len() - rtc::STATE_LENGTH;
+                        rtc.load_state(&data[backup_length..]);
+                        backup_length
+                    },
+                    _ => data.len(),
+                };
+
+                cartridge.backup.load_contents(&data[..backup_length]);
+                Ok(())
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    // called regularly while running; writes once the game has gone quiet
+    pub fn update(&mut self, cartridge: &mut Cartridge) -> io::Result<()> {
+        if cartridge.take_dirty() {
+            self.last_write = Some(Instant::now());
+        }
+
+        match self.last_write {
+            Some(time) if time.elapsed() >= DEBOUNCE => self.flush(cartridge),
+            _ => Ok(()),
+        }
+    }
+
+    // writes any outstanding changes straight away, for shutting down
+    pub fn flush(&mut self, cartridge: &mut Cartridge) -> io::Result<()> {
+        if cartridge.take_dirty() || self.last_write.is_some() {
+            let mut data = cartridge.backup.contents().to_vec();
+            if let Some(rtc) = cartridge.gpio.as_ref().and_then(|gpio| gpio.rtc.as_ref()) {
+                data.extend_from_slice(&rtc.save_state());
+            }
+
+            self.write(&data)?;
+            self.last_write = None;
+        }
+
+        Ok(())
+    }
+
+    // goes through a temporary file and a rename so a crash part way through
+    // leaves either the old save or the new one, never half of each
+    fn write(&self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut temp_name = self.path.clone().into_os_string();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&temp_path, &self.path)
+    }
+}
@@ -2,6 +2,8 @@ use crate::core::bus::Memory;
 use crate::core::bus::BusAccess;
 use crate::constants::register_index;
 use crate::constants::register_initial;
+use crate::constants::register_reset;
+use crate::constants::mode_bits;
 use crate::constants::flag_masks;
 use crate::constants::CONDITION_MASK;
 use crate::constants::condition_codes;
@@ -33,6 +35,7 @@ impl Default for ARM7TDMI {
         cpu.register[15] = register_initial::PC;
         cpu.register[16] = register_initial::CPSR;
         cpu.register[17] = register_initial::SP_IRQ;
+        cpu.register[28] = register_initial::SP_SVC;
         cpu.register[34] = register_initial::SP_UND;
 
         cpu
@@ -40,6 +43,56 @@ impl Default for ARM7TDMI {
 }
 
 impl ARM7TDMI {
+    // state of the core when it comes out of reset, before the bios has run
+    pub fn reset(&mut self) {
+        self.register = [0; 37];
+        self.register[register_index::PC] = register_reset::PC;
+        self.register[register_index::CPSR] = register_reset::CPSR;
+        self.bank_registers();
+    }
+
+    pub fn rreg(&self, n: usize) -> u32 {
+        self.register[self.idx[n]]
+    }
+
+    pub fn wreg(&mut self, n: usize, data: u32) {
+        self.register[self.idx[n]] = data;
+    }
+
+    // switches into the exception mode with irqs masked and jumps to its
+    // vector, keeping the old cpsr in the new mode's spsr
+    pub fn enter_exception(&mut self, mode: u32, vector: u32, return_addr: u32) {
+        let cpsr = self.register[register_index::CPSR];
+        self.register[register_index::CPSR] = (cpsr & !(mode_bits::MASK | flag_masks::T)) | mode | flag_masks::I;
+        self.bank_registers();
+
+        self.register[self.spsr] = cpsr;
+        self.register[self.idx[14]] = return_addr;
+        self.register[register_index::PC] = vector;
+    }
+
+    // points r8-r14 and the spsr at the banked copies for the current mode
+    pub fn bank_registers(&mut self) {
+        let mode = self.register[register_index::CPSR] & mode_bits::MASK;
+
+        for r in 8..=12 {
+            self.idx[r] = if mode == mode_bits::FIQ { r + 12 } else { r };
+        }
+
+        let (sp, lr, spsr) = match mode {
+            mode_bits::FIQ => (register_index::SP_FIQ, register_index::LR_FIQ, register_index::SPSR_FIQ),
+            mode_bits::IRQ => (register_index::SP_IRQ, register_index::LR_IRQ, register_index::SPSR_IRQ),
+            mode_bits::SVC => (register_index::SP_SVC, register_index::LR_SVC, register_index::SPSR_SVC),
+            mode_bits::ABT => (register_index::SP_ABT, register_index::LR_ABT, register_index::SPSR_ABT),
+            mode_bits::UND => (register_index::SP_UND, register_index::LR_UND, register_index::SPSR_UND),
+            _ => (register_index::SP_USR, register_index::LR_USR, register_index::SPSR_UND),
+        };
+
+        self.idx[13] = sp;
+        self.idx[14] = lr;
+        self.spsr = spsr;
+    }
+
     // ARM INSTRUCTIONS
     fn BX(&mut self, opcode: u32) {
         let rm: usize = (opcode & 0b0111) as usize;
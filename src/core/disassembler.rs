@@ -1,3 +1,8 @@
+use std::fmt;
+
+use crate::core::bus::Memory;
+use crate::core::symbols::SymbolTable;
+
 pub enum InstructionSet {
     ARM(u32),
     THUMB(u16),
@@ -127,6 +132,71 @@ fn disassemble_thumb(opcode: u16) -> Instruction {
     }
 }
 
+// count instructions from addr, a line each, with the name of any symbol
+// that starts along the way on a line of its own and branch targets shown
+// as labels. a thumb bl is shown on its first half with the whole target
+pub fn listing(memory: &Memory, addr: u32, thumb: bool, count: usize, symbols: &SymbolTable) -> Vec<String> {
+    let width = if thumb { 2 } else { 4 };
+    let mut lines = Vec::new();
+
+    for pc in (0..count as u32).map(|i| addr.wrapping_add(i * width)) {
+        if let Some((symbol, 0)) = symbols.lookup(pc) {
+            lines.push(format!("{}:", symbol.name));
+        }
+
+        let (inset, opcode) = if thumb {
+            let op = memory.rhalf(pc as usize);
+            (InstructionSet::THUMB(op), format!("{:04x}    ", op))
+        } else {
+            let op = memory.rword(pc as usize);
+            (InstructionSet::ARM(op), format!("{:08x}", op))
+        };
+
+        // the high half of a bl has bit 11 clear
+        let target = match inset {
+            InstructionSet::THUMB(op) if thumb_long_branch_with_link(op) && (op & (1 << 11)) == 0 => {
+                Some(long_branch_target(op, memory.rhalf(pc as usize + 2), pc))
+            },
+            _ => branch_target(&inset, pc),
+        };
+
+        let mut line = format!("  {:#010x}  {}  {}", pc, opcode, disassemble(inset));
+        if let Some(target) = target {
+            line.push_str(&format!(" -> {}", symbols.label(target)));
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+// where a relative branch at pc goes, for labelling it with a symbol. the
+// pc reads two instructions ahead. the halves of a thumb bl only make
+// sense together, so those are left to the caller
+pub fn branch_target(inset: &InstructionSet, pc: u32) -> Option<u32> {
+    match *inset {
+        InstructionSet::ARM(op) if arm_branch_and_branch_with_link(op) => {
+            let offset = ((op << 8) as i32) >> 6;
+            Some(pc.wrapping_add(8).wrapping_add(offset as u32))
+        },
+        InstructionSet::THUMB(op) if thumb_conditional_branch(op) && !thumb_software_interrupt(op) => {
+            let offset = ((op << 8) as i16 as i32) >> 7;
+            Some(pc.wrapping_add(4).wrapping_add(offset as u32))
+        },
+        InstructionSet::THUMB(op) if thumb_unconditional_branch(op) => {
+            let offset = ((op << 5) as i16 as i32) >> 4;
+            Some(pc.wrapping_add(4).wrapping_add(offset as u32))
+        },
+        _ => None,
+    }
+}
+
+// the target of a thumb bl from its two halves, high offset first
+pub fn long_branch_target(high: u16, low: u16, pc: u32) -> u32 {
+    let offset = (((high as u32) << 21) as i32 >> 9) as u32 | (((low as u32) & 0x7FF) << 1);
+    pc.wrapping_add(4).wrapping_add(offset)
+}
+
 fn arm_branch_and_branch_exchange(opcode: u32) -> bool {
     let format: u32 = 0b0000_0001_0010_1111_1111_1111_0001_0000;
     let mask: u32 = 0b0000_1111_1111_1111_1111_1111_1111_0000;
@@ -353,3 +423,41 @@ fn thumb_move_shifted_register(opcode: u16) -> bool {
     (opcode & mask) == format
 }
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Instruction::BranchAndBranchExchange => "branch and exchange",
+            Instruction::BlockDataTransfer => "block data transfer",
+            Instruction::BranchAndBranchWithLink => "branch",
+            Instruction::SoftwareInterruptA | Instruction::SoftwareInterruptT => "software interrupt",
+            Instruction::Undefined | Instruction::UndefinedInstruction => "undefined",
+            Instruction::SingleDataTransfer => "single data transfer",
+            Instruction::SingleDataSwap => "single data swap",
+            Instruction::MultiplyAndMultiplyLong => "multiply",
+            Instruction::HalfwordDataTransferR => "halfword transfer, register offset",
+            Instruction::HalfwordDataTransferI => "halfword transfer, immediate offset",
+            Instruction::PSRTransferMRS => "psr transfer (mrs)",
+            Instruction::PSRTransferMSR => "psr transfer (msr)",
+            Instruction::DataProcessing => "data processing",
+            Instruction::UnconditionalBranch => "unconditional branch",
+            Instruction::ConditionalBranch => "conditional branch",
+            Instruction::MultipleLoadStore => "multiple load/store",
+            Instruction::LongBranchWithLink => "long branch with link",
+            Instruction::AddOffsetToSP => "add offset to sp",
+            Instruction::PushPopRegister => "push/pop registers",
+            Instruction::LoadStoreHalfword => "load/store halfword",
+            Instruction::SPRelativeLoadStore => "sp-relative load/store",
+            Instruction::LoadAddress => "load address",
+            Instruction::LoadStoreImmediateOffset => "load/store, immediate offset",
+            Instruction::LoadStoreRegisterOffset => "load/store, register offset",
+            Instruction::LoadStoreSignExtended => "load/store sign-extended",
+            Instruction::PCRelativeLoad => "pc-relative load",
+            Instruction::HiRegisterOperation => "hi register operation",
+            Instruction::ALUOperations => "alu operation",
+            Instruction::MoveCompareAddSubImmediate => "move/compare/add/subtract immediate",
+            Instruction::AddSubtract => "add/subtract",
+            Instruction::MoveShiftedRegister => "move shifted register",
+        };
+        write!(f, "{}", name)
+    }
+}
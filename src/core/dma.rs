@@ -0,0 +1,169 @@
+use crate::core::ppu::io16;
+use crate::core::ppu::io32;
+use crate::constants::dmacnt;
+use crate::constants::io_address;
+use crate::constants::memory_region;
+
+// each channel has DMAxSAD, DMAxDAD, DMAxCNT_L and DMAxCNT_H
+pub const CHANNEL_STRIDE: usize = 12;
+pub const CONTROL_OFFSET: usize = 10;
+const COUNT_OFFSET: usize = 8;
+
+// what each channel can reach. dma0 stays inside the console, only dma3
+// can write to the cartridge, and only dma3 has a full 16 bit count
+const SOURCE_MASKS: [u32; 4] = [0x07FF_FFFF, 0x0FFF_FFFF, 0x0FFF_FFFF, 0x0FFF_FFFF];
+const DEST_MASKS: [u32; 4] = [0x07FF_FFFF, 0x07FF_FFFF, 0x07FF_FFFF, 0x0FFF_FFFF];
+const COUNT_MASKS: [u32; 4] = [0x3FFF, 0x3FFF, 0x3FFF, 0xFFFF];
+
+// the sound dmas always move four words into the fifo
+pub const FIFO_WORDS: u32 = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timing {
+    Immediate,
+    VBlank,
+    HBlank,
+    // sound fifo refills for dma1 and 2, video capture for dma3
+    Special,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AddressControl {
+    Increment,
+    Decrement,
+    Fixed,
+    // increments, and goes back to DMAxDAD each time a repeat starts
+    Reload,
+}
+
+impl AddressControl {
+    fn from_bits(bits: u16) -> AddressControl {
+        match bits & 0x3 {
+            0 => AddressControl::Increment,
+            1 => AddressControl::Decrement,
+            2 => AddressControl::Fixed,
+            _ => AddressControl::Reload,
+        }
+    }
+
+    pub fn step(&self, width: u32) -> u32 {
+        match self {
+            AddressControl::Increment | AddressControl::Reload => width,
+            AddressControl::Decrement => width.wrapping_neg(),
+            AddressControl::Fixed => 0,
+        }
+    }
+}
+
+// DMAxCNT_H taken apart
+#[derive(Clone, Copy)]
+pub struct Control {
+    pub dest: AddressControl,
+    pub source: AddressControl,
+    pub repeat: bool,
+    pub word: bool,
+    pub game_pak_drq: bool,
+    pub timing: Timing,
+    pub irq: bool,
+    pub enabled: bool,
+}
+
+impl Control {
+    pub fn from_bits(bits: u16) -> Control {
+        Control {
+            dest: AddressControl::from_bits(bits >> dmacnt::DEST_SHIFT),
+            source: AddressControl::from_bits(bits >> dmacnt::SOURCE_SHIFT),
+            repeat: (bits & dmacnt::REPEAT) != 0,
+            word: (bits & dmacnt::WORD) != 0,
+            game_pak_drq: (bits & dmacnt::GAME_PAK_DRQ) != 0,
+            timing: match (bits >> dmacnt::TIMING_SHIFT) & 0x3 {
+                0 => Timing::Immediate,
+                1 => Timing::VBlank,
+                2 => Timing::HBlank,
+                _ => Timing::Special,
+            },
+            irq: (bits & dmacnt::IRQ) != 0,
+            enabled: (bits & dmacnt::ENABLE) != 0,
+        }
+    }
+}
+
+// the internal copies of a channel's addresses and count. they're loaded
+// from the registers when the channel is enabled and move on as it runs,
+// so a repeating transfer carries on from where the last one stopped
+#[derive(Clone, Copy, Default)]
+pub struct Channel {
+    pub source: u32,
+    pub dest: u32,
+    pub count: u32,
+}
+
+pub struct Dma {
+    pub channels: [Channel; 4],
+}
+
+impl Dma {
+    pub fn new() -> Dma {
+        Dma { channels: [Channel::default(); 4] }
+    }
+
+    // a channel has just been enabled
+    pub fn latch(&mut self, index: usize, ram: &[u8]) {
+        let base = register(index);
+        let channel = &mut self.channels[index];
+
+        channel.source = io32(ram, base) & SOURCE_MASKS[index];
+        channel.dest = io32(ram, base + 4) & DEST_MASKS[index];
+        self.reload_count(index, ram);
+    }
+
+    // a count of 0 means the most the channel can do
+    pub fn reload_count(&mut self, index: usize, ram: &[u8]) {
+        let count = io16(ram, register(index) + COUNT_OFFSET) as u32 & COUNT_MASKS[index];
+        self.channels[index].count = if count == 0 { COUNT_MASKS[index] + 1 } else { count };
+    }
+
+    pub fn reload_dest(&mut self, index: usize, ram: &[u8]) {
+        self.channels[index].dest = io32(ram, register(index) + 4) & DEST_MASKS[index];
+    }
+
+    // where the addresses go after each unit. the cartridge bus can only
+    // count upwards, so reads from rom always increment
+    pub fn steps(&self, index: usize, control: &Control, width: u32) -> (u32, u32) {
+        let source = self.channels[index].source as usize;
+        let source_step = if (memory_region::ROM..memory_region::SRAM).contains(&source) {
+            width
+        } else {
+            control.source.step(width)
+        };
+
+        (source_step, control.dest.step(width))
+    }
+
+    pub fn advance(&mut self, index: usize, source_step: u32, dest_step: u32) {
+        let channel = &mut self.channels[index];
+        channel.source = channel.source.wrapping_add(source_step) & SOURCE_MASKS[index];
+        channel.dest = channel.dest.wrapping_add(dest_step) & DEST_MASKS[index];
+    }
+}
+
+pub fn register(index: usize) -> usize {
+    io_address::DMA0SAD + index * CHANNEL_STRIDE
+}
+
+pub fn control(ram: &[u8], index: usize) -> Control {
+    Control::from_bits(io16(ram, register(index) + CONTROL_OFFSET))
+}
+
+// only DMAxCNT_H can be read back, and the drq bit only exists on dma3
+pub fn read(addr: usize, ram: &[u8]) -> u8 {
+    let offset = (addr - io_address::DMA0SAD) % CHANNEL_STRIDE;
+    let index = (addr - io_address::DMA0SAD) / CHANNEL_STRIDE;
+
+    match offset {
+        CONTROL_OFFSET => ram[addr] & 0xE0,
+        11 if index == 3 => ram[addr],
+        11 => ram[addr] & !((dmacnt::GAME_PAK_DRQ >> 8) as u8),
+        _ => 0,
+    }
+}
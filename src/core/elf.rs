@@ -0,0 +1,261 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::symbols::Symbol;
+use crate::core::symbols::SymbolTable;
+use crate::constants::cartridge_header;
+use crate::constants::memory_region;
+
+// offsets into the elf32 file header
+mod header {
+    pub const CLASS: usize = 0x04;
+    pub const DATA: usize = 0x05;
+    pub const TYPE: usize = 0x10;
+    pub const MACHINE: usize = 0x12;
+    pub const ENTRY: usize = 0x18;
+    pub const PHOFF: usize = 0x1C;
+    pub const SHOFF: usize = 0x20;
+    pub const PHENTSIZE: usize = 0x2A;
+    pub const PHNUM: usize = 0x2C;
+    pub const SHENTSIZE: usize = 0x2E;
+    pub const SHNUM: usize = 0x30;
+    pub const LENGTH: usize = 0x34;
+}
+
+const MAGIC: &[u8] = b"\x7FELF";
+const CLASS_32: u8 = 1;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const TYPE_EXECUTABLE: u16 = 2;
+const MACHINE_ARM: u16 = 40;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+
+// one PT_LOAD segment, already padded out to its size in memory
+pub struct Segment {
+    pub addr: u32,
+    pub data: Box<[u8]>,
+}
+
+// an executable as linked by devkitarm, either for the cartridge or for
+// multiboot. sections are ignored apart from the symbol table
+pub struct Elf {
+    pub entry_point: u32,
+    pub segments: Vec<Segment>,
+    pub symbols: SymbolTable,
+}
+
+pub enum ElfError {
+    Io(io::Error),
+    NotElf,
+    Unsupported(&'static str),
+    Truncated,
+    SegmentOutOfRange { addr: u32, size: u32 },
+}
+
+impl Elf {
+    pub fn load(path: &Path) -> Result<Elf, ElfError> {
+        let data = fs::read(path)?;
+        Elf::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Elf, ElfError> {
+        if data.len() < header::LENGTH || &data[..4] != MAGIC {
+            return Err(ElfError::NotElf);
+        }
+
+        if data[header::CLASS] != CLASS_32 {
+            return Err(ElfError::Unsupported("only 32 bit elf files are supported"));
+        }
+        if data[header::DATA] != DATA_LITTLE_ENDIAN {
+            return Err(ElfError::Unsupported("only little endian elf files are supported"));
+        }
+        if half(data, header::TYPE)? != TYPE_EXECUTABLE {
+            return Err(ElfError::Unsupported("not an executable, was it linked?"));
+        }
+        if half(data, header::MACHINE)? != MACHINE_ARM {
+            return Err(ElfError::Unsupported("not built for arm"));
+        }
+
+        Ok(Elf {
+            entry_point: word(data, header::ENTRY)?,
+            segments: segments(data)?,
+            symbols: symbols(data)?,
+        })
+    }
+
+    // cartridge programs are linked at 0x0800_0000, so their segments there
+    // are stitched into a rom image for the cartridge slot
+    pub fn rom_image(&self) -> Option<Vec<u8>> {
+        let rom = |segment: &&Segment| segment.addr as usize >= memory_region::ROM;
+
+        let end = self.segments.iter().filter(rom)
+            .map(|segment| segment.addr as usize - memory_region::ROM + segment.data.len())
+            .max()?;
+
+        // short images still need room for the header fields to be read
+        let mut image = vec![0u8; end.max(cartridge_header::LENGTH)];
+        for segment in self.segments.iter().filter(rom) {
+            let offset = segment.addr as usize - memory_region::ROM;
+            image[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+        }
+
+        Some(image)
+    }
+
+    // everything that isn't rom gets copied straight into memory
+    pub fn ram_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|segment| (segment.addr as usize) < memory_region::ROM)
+    }
+}
+
+// segments are placed at their physical (load) address. initialised data
+// linked to run from iwram is loaded into rom and copied across by crt0
+fn segments(data: &[u8]) -> Result<Vec<Segment>, ElfError> {
+    let offset = word(data, header::PHOFF)? as usize;
+    let entry_size = half(data, header::PHENTSIZE)? as usize;
+    let count = half(data, header::PHNUM)? as usize;
+
+    let mut segments = Vec::new();
+
+    for i in 0..count {
+        let entry = offset + i * entry_size;
+        if word(data, entry)? != PT_LOAD {
+            continue;
+        }
+
+        let file_offset = word(data, entry + 0x04)? as usize;
+        let addr = word(data, entry + 0x0C)?;
+        let file_size = word(data, entry + 0x10)? as usize;
+        let memory_size = word(data, entry + 0x14)?;
+
+        if memory_size == 0 {
+            continue;
+        }
+
+        if !loadable(addr, memory_size) {
+            return Err(ElfError::SegmentOutOfRange { addr, size: memory_size });
+        }
+
+        let contents = data.get(file_offset..file_offset + file_size).ok_or(ElfError::Truncated)?;
+        let mut segment = vec![0u8; memory_size as usize];
+        let length = file_size.min(segment.len());
+        segment[..length].copy_from_slice(&contents[..length]);
+
+        segments.push(Segment { addr, data: segment.into_boxed_slice() });
+    }
+
+    Ok(segments)
+}
+
+// the cartridge rom, or any of the ram regions other than i/o
+fn loadable(addr: u32, size: u32) -> bool {
+    let start = addr as usize;
+    let end = start + size as usize;
+    let rom_end = memory_region::ROM + cartridge_header::MAX_ROM_SIZE;
+
+    let within = |region_start: usize, region_end: usize| start >= region_start && end <= region_end;
+    within(memory_region::EWRAM, memory_region::IO) || within(memory_region::PALETTE, memory_region::ROM) || within(memory_region::ROM, rom_end)
+}
+
+// keeps named functions and data objects. the low bit of a thumb function's
+// address is set for interworking and isn't part of the address
+fn symbols(data: &[u8]) -> Result<SymbolTable, ElfError> {
+    let offset = word(data, header::SHOFF)? as usize;
+    let entry_size = half(data, header::SHENTSIZE)? as usize;
+    let count = half(data, header::SHNUM)? as usize;
+
+    let section = |index: usize| -> Result<(u32, usize, usize, usize), ElfError> {
+        let entry = offset + index * entry_size;
+        Ok((
+            word(data, entry + 0x04)?,
+            word(data, entry + 0x10)? as usize,
+            word(data, entry + 0x14)? as usize,
+            word(data, entry + 0x18)? as usize,
+        ))
+    };
+
+    // a stripped elf has no symbol table, which leaves the table empty
+    let mut symbols = Vec::new();
+
+    for index in 0..count {
+        let (kind, symtab_offset, symtab_size, link) = section(index)?;
+        if kind != SHT_SYMTAB {
+            continue;
+        }
+
+        let (_, strtab_offset, strtab_size, _) = section(link)?;
+        let strtab = data.get(strtab_offset..strtab_offset + strtab_size).ok_or(ElfError::Truncated)?;
+
+        for entry in (symtab_offset..symtab_offset + symtab_size).step_by(16) {
+            let name = word(data, entry)? as usize;
+            let mut addr = word(data, entry + 0x04)?;
+            let size = word(data, entry + 0x08)?;
+            let info = *data.get(entry + 0x0C).ok_or(ElfError::Truncated)?;
+            let section_index = half(data, entry + 0x0E)?;
+
+            let kind = info & 0xF;
+            if (kind != STT_FUNC && kind != STT_OBJECT) || section_index == SHN_UNDEF {
+                continue;
+            }
+
+            if kind == STT_FUNC {
+                addr &= !1;
+            }
+
+            let name = match strtab.get(name..).and_then(|rest| rest.split(|&byte| byte == 0).next()) {
+                Some(name) if !name.is_empty() => String::from_utf8_lossy(name).into_owned(),
+                _ => continue,
+            };
+
+            symbols.push(Symbol { name, addr, size });
+        }
+    }
+
+    Ok(SymbolTable::new(symbols))
+}
+
+fn half(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(ElfError::Truncated),
+    }
+}
+
+fn word(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(ElfError::Truncated),
+    }
+}
+
+impl From<io::Error> for ElfError {
+    fn from(err: io::Error) -> ElfError {
+        ElfError::Io(err)
+    }
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::Io(err) => write!(f, "could not read elf: {}", err),
+            ElfError::NotElf => write!(f, "not an elf file"),
+            ElfError::Unsupported(reason) => write!(f, "unsupported elf: {}", reason),
+            ElfError::Truncated => write!(f, "elf is truncated"),
+            ElfError::SegmentOutOfRange { addr, size } => {
+                write!(f, "segment of {} bytes at {:#010x} is outside loadable memory", size, addr)
+            },
+        }
+    }
+}
+
+impl fmt::Debug for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,291 @@
+use std::io;
+use std::path::Path;
+
+use crate::core::audio::record::Recorder;
+use crate::core::audio::stream;
+use crate::core::audio::stream::AudioStream;
+use crate::core::bios::Bios;
+use crate::core::bios::hle;
+use crate::core::bus::Memory;
+use crate::core::cartridge::Cartridge;
+use crate::core::cartridge::save_file::SaveFile;
+use crate::core::cpu::ARM7TDMI;
+use crate::core::disassembler;
+use crate::core::elf::Elf;
+use crate::core::multiboot::Multiboot;
+use crate::core::ppu::rgb888;
+use crate::core::ppu::viewer;
+use crate::core::screenshot;
+use crate::core::screenshot::ImageFormat;
+use crate::core::symbols::SymbolTable;
+use crate::core::video::color::ColorCorrection;
+use crate::core::video::color::ColorFilter;
+use crate::core::video::scale::Frame;
+use crate::core::video::scale::Scaler;
+use crate::constants::io_address;
+use crate::constants::lcd;
+use crate::constants::bios_address;
+use crate::constants::memory_region;
+use crate::constants::register_initial;
+use crate::constants::register_index;
+use crate::constants::mode_bits;
+use crate::constants::exception_vector;
+use crate::constants::flag_masks;
+
+pub struct GameBoyAdvance {
+    pub cpu: ARM7TDMI,
+    pub memory: Memory,
+    pub bios_loaded: bool,
+    pub save_file: Option<SaveFile>,
+    // where a direct boot starts running, the cartridge or ewram
+    pub entry_point: u32,
+    // names for addresses when the program came with them
+    pub symbols: SymbolTable,
+    pub color_filter: ColorFilter,
+    // the last finished frame as 8 bit rgb, after colour correction
+    pub display: Vec<u8>,
+    pub scaler: Scaler,
+    // the sound at the host's rate, for the frontend to play
+    pub audio: AudioStream,
+    recorder: Option<Recorder>,
+}
+
+impl GameBoyAdvance {
+    pub fn new() -> GameBoyAdvance {
+        let mut gba = GameBoyAdvance {
+            cpu: Default::default(),
+            memory: Memory::new(),
+            bios_loaded: false,
+            save_file: None,
+            entry_point: register_initial::PC,
+            symbols: SymbolTable::default(),
+            color_filter: ColorFilter::new(ColorCorrection::None, 0.0),
+            display: Vec::new(),
+            scaler: Scaler::Nearest(1),
+            audio: AudioStream::new(stream::DEFAULT_RATE),
+            recorder: None,
+        };
+
+        gba.memory.whalf(io_address::KEYINPUT, 0x03FF);
+
+        gba
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.memory.load_cartridge(cartridge);
+        self.entry_point = register_initial::PC;
+    }
+
+    // copies the image to the start of ewram, where the bios would have put
+    // it after receiving it over the cable
+    pub fn load_multiboot(&mut self, multiboot: Multiboot) {
+        for (i, byte) in multiboot.image.iter().enumerate() {
+            self.memory.wbyte(memory_region::EWRAM + i, *byte);
+        }
+
+        self.entry_point = register_initial::MULTIBOOT_PC;
+    }
+
+    // the rom part of a cartridge elf goes in with insert_cartridge first,
+    // this places everything linked to run from ram
+    pub fn load_elf(&mut self, elf: Elf) {
+        for segment in elf.ram_segments() {
+            for (i, byte) in segment.data.iter().enumerate() {
+                self.memory.wbyte(segment.addr as usize + i, *byte);
+            }
+        }
+
+        self.entry_point = elf.entry_point;
+        self.symbols = elf.symbols;
+    }
+
+    // maps the image into the bios region and resets the cpu to the reset
+    // vector in svc mode. nothing from the bios runs until there's an
+    // instruction loop
+    pub fn boot_bios(&mut self, bios: Bios) {
+        self.memory.load_bios(&bios.image);
+        self.bios_loaded = true;
+        self.cpu.reset();
+    }
+
+    // skip the bios and leave the cpu and i/o registers how the bios would
+    // have left them on its way into the cartridge or multiboot program
+    pub fn boot_direct(&mut self) {
+        self.cpu = Default::default();
+        self.cpu.register[register_index::PC] = self.entry_point & !1;
+
+        // an odd entry point is how an elf says it starts in thumb code
+        if (self.entry_point & 1) != 0 {
+            self.cpu.register[register_index::CPSR] |= flag_masks::T;
+        }
+        self.memory.wbyte(io_address::POSTFLG, 1);
+        self.memory.whalf(io_address::SOUNDBIAS, 0x0200);
+
+        // identity scaling for the affine backgrounds
+        for params in [io_address::BG2PA, io_address::BG3PA] {
+            self.memory.whalf(params, 0x0100);
+            self.memory.whalf(params + 6, 0x0100);
+        }
+
+        // tells a SoftReset to go back to ewram rather than the cartridge
+        let multiboot = (self.entry_point as usize & 0xFF00_0000) == memory_region::EWRAM;
+        self.memory.wbyte(bios_address::MULTIBOOT_FLAG, multiboot as u8);
+
+        // interrupts still have to go through something at the irq vector
+        self.memory.load_bios(&hle::stub_image());
+    }
+
+    // called with the cycles each instruction took, keeping the rest of the
+    // hardware in step with the cpu
+    pub fn advance(&mut self, cycles: u32) {
+        self.memory.tick(cycles);
+
+        // the cpu sits out any dma, while everything else carries on
+        loop {
+            let stall = std::mem::take(&mut self.memory.stall_cycles);
+            if stall == 0 {
+                break;
+            }
+            self.memory.tick(stall);
+        }
+
+        self.check_interrupts();
+    }
+
+    // runs until the display reaches the start of its next vblank, when a
+    // whole picture is in the framebuffer, then passes the frame's sound on
+    // to the audio stream. there's no instruction loop yet,
+    // so the cpu sits out the frame as if halted and the hardware moves on
+    // an event at a time
+    pub fn run_frame(&mut self) {
+        let frame = self.memory.ppu.frame;
+
+        while self.memory.ppu.frame == frame {
+            let cycles = self.memory.ppu.cycles_to_event();
+            self.advance(cycles);
+        }
+
+        self.display = self.color_filter.apply(&self.memory.ppu.framebuffer);
+
+        let samples = self.memory.apu.take_samples();
+        let channel_samples = self.memory.apu.take_channel_samples();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.queue(&samples, &channel_samples);
+        }
+        self.audio.push(&samples);
+    }
+
+    // the picture as 240x160 15 bit colours row by row, whole once
+    // run_frame returns
+    pub fn framebuffer(&self) -> &[u16] {
+        &self.memory.ppu.framebuffer
+    }
+
+    // the last frame ready to show, colour corrected and scaled up
+    pub fn output(&self) -> Frame {
+        let rgb = if self.display.is_empty() {
+            self.framebuffer().iter().flat_map(|&color| rgb888(color)).collect()
+        } else {
+            self.display.clone()
+        };
+
+        self.scaler.scale(&Frame { width: lcd::WIDTH, height: lcd::HEIGHT, rgb })
+    }
+
+    // raw 15 bit dumps are always the console's own pixels, the other
+    // formats take what would be on the display
+    pub fn save_screenshot(&self, path: &Path, format: ImageFormat) -> io::Result<()> {
+        if format == ImageFormat::Rgb555 {
+            return screenshot::save(path, self.framebuffer(), lcd::WIDTH, lcd::HEIGHT, format);
+        }
+
+        let frame = self.output();
+        screenshot::save_rgb(path, &frame.rgb, frame.width, frame.height, format)
+    }
+
+    // writes the palette, tiles, background maps and sprites out as images
+    pub fn dump_vram(&self, dir: &Path, palette_bank: usize) -> io::Result<()> {
+        viewer::dump(self.memory.ram(), dir, palette_bank)
+    }
+
+    // takes the irq exception if one is pending and the cpu isn't masking
+    // them. pc already holds the next instruction, and the handler returns
+    // with subs pc, lr, #4
+    pub fn check_interrupts(&mut self) {
+        let masked = (self.cpu.register[register_index::CPSR] & flag_masks::I) != 0;
+
+        if !masked && self.memory.interrupt_pending() {
+            let return_addr = self.cpu.register[register_index::PC].wrapping_add(4);
+            self.cpu.enter_exception(mode_bits::IRQ, exception_vector::IRQ, return_addr);
+        }
+    }
+
+    // from the entry point, read as thumb when it's odd like boot_direct does
+    pub fn disassemble(&self, count: usize) -> Vec<String> {
+        let thumb = (self.entry_point & 1) != 0;
+        disassembler::listing(&self.memory, self.entry_point & !1, thumb, count, &self.symbols)
+    }
+
+    // with a real bios the swi vectors into it, otherwise the call is
+    // handled here and returns straight to the next instruction. waiting on
+    // the cpu to decode swi
+    #[allow(dead_code)]
+    pub fn software_interrupt(&mut self, comment: u8) {
+        if self.bios_loaded {
+            let return_addr = self.cpu.register[register_index::PC];
+            self.cpu.enter_exception(mode_bits::SVC, exception_vector::SWI, return_addr);
+        } else {
+            hle::software_interrupt(&mut self.cpu, &mut self.memory, comment);
+        }
+    }
+
+    // records the sound to a wav file, and each channel to its own beside
+    // it if asked. any recording already going is finished first
+    pub fn start_recording(&mut self, path: &Path, separate_channels: bool) -> io::Result<()> {
+        self.stop_recording()?;
+        self.recorder = Some(Recorder::start(path, separate_channels)?);
+        self.memory.apu.record_channels = separate_channels;
+        Ok(())
+    }
+
+    // writes out the sound from the frames run since the last call
+    pub fn update_recording(&mut self) -> io::Result<()> {
+        match &mut self.recorder {
+            Some(recorder) => recorder.write(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        self.memory.apu.record_channels = false;
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    // reads the existing save into the cartridge and keeps the file around
+    // for writing changes back to
+    pub fn attach_save_file(&mut self, save_file: SaveFile) -> io::Result<()> {
+        if let Some(cartridge) = &mut self.memory.cartridge {
+            save_file.load(cartridge)?;
+        }
+
+        self.save_file = Some(save_file);
+        Ok(())
+    }
+
+    pub fn update_save(&mut self) -> io::Result<()> {
+        match (&mut self.save_file, &mut self.memory.cartridge) {
+            (Some(save_file), Some(cartridge)) => save_file.update(cartridge),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        match (&mut self.save_file, &mut self.memory.cartridge) {
+            (Some(save_file), Some(cartridge)) => save_file.flush(cartridge),
+            _ => Ok(()),
+        }
+    }
+}
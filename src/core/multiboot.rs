@@ -0,0 +1,81 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::cartridge::CartridgeError;
+use crate::core::cartridge::Header;
+use crate::constants::cartridge_header;
+use crate::constants::multiboot_header;
+use crate::constants::EWRAM_SIZE;
+
+// a program sent over the link cable instead of run from a cartridge. it
+// carries the same header as a rom followed by a few multiboot fields, and
+// is built to run from the start of ewram
+pub struct Multiboot {
+    pub header: Header,
+    pub image: Box<[u8]>,
+}
+
+pub enum MultibootError {
+    Io(io::Error),
+    TooSmall(usize),
+    TooLarge(usize),
+    Header(CartridgeError),
+}
+
+impl Multiboot {
+    pub fn load(path: &Path) -> Result<Multiboot, MultibootError> {
+        let data = fs::read(path)?;
+        Multiboot::from_bytes(data)
+    }
+
+    pub fn from_bytes(mut data: Vec<u8>) -> Result<Multiboot, MultibootError> {
+        if data.len() < multiboot_header::LENGTH {
+            return Err(MultibootError::TooSmall(data.len()));
+        }
+
+        if data.len() > EWRAM_SIZE {
+            return Err(MultibootError::TooLarge(data.len()));
+        }
+
+        let header = Header::parse(&data[..cartridge_header::LENGTH]).map_err(MultibootError::Header)?;
+
+        // the bios fills these in during the transfer to tell the program how
+        // it arrived; pretend to be the first slave on a multiplay cable
+        data[multiboot_header::BOOT_MODE] = multiboot_header::BOOT_MODE_MULTIPLAY;
+        data[multiboot_header::SLAVE_ID] = 1;
+
+        Ok(Multiboot {
+            header,
+            image: data.into_boxed_slice(),
+        })
+    }
+}
+
+impl From<io::Error> for MultibootError {
+    fn from(err: io::Error) -> MultibootError {
+        MultibootError::Io(err)
+    }
+}
+
+impl fmt::Display for MultibootError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultibootError::Io(err) => write!(f, "could not read multiboot image: {}", err),
+            MultibootError::TooSmall(size) => {
+                write!(f, "image is {} bytes, smaller than the {} byte multiboot header", size, multiboot_header::LENGTH)
+            },
+            MultibootError::TooLarge(size) => {
+                write!(f, "image is {} bytes, larger than the {} bytes of ewram", size, EWRAM_SIZE)
+            },
+            MultibootError::Header(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl fmt::Debug for MultibootError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,286 @@
+use crate::core::ppu::effects::Blend;
+use crate::core::ppu::effects::Mosaic;
+use crate::core::ppu::sprite::ObjLine;
+use crate::constants::bgcnt;
+use crate::constants::dispcnt;
+use crate::constants::dispstat;
+use crate::constants::io_address;
+use crate::constants::memory_region;
+use crate::constants::lcd;
+
+pub mod bitmap;
+pub mod effects;
+pub mod sprite;
+pub mod tiled;
+pub mod viewer;
+
+// one background's pixels for a scanline, None where it is transparent
+pub type Layer = [Option<u16>; lcd::WIDTH];
+
+// a layer's pixel competing to be seen
+#[derive(Clone, Copy)]
+struct Candidate {
+    priority: u16,
+    rank: usize,
+    layer: usize,
+    color: u16,
+}
+
+impl Candidate {
+    fn key(&self) -> (u16, usize) {
+        (self.priority, self.rank)
+    }
+}
+
+// white, shown while the display is force blanked
+const BLANK_COLOR: u16 = 0x7FFF;
+
+// the internal reference point of an affine background. it's copied from
+// BGxX/BGxY at the start of each frame and whenever the game writes them,
+// then steps by pb/pd every line. pa and pc are the per pixel steps
+#[derive(Clone, Copy, Default)]
+pub struct AffineState {
+    pub x: i32,
+    pub y: i32,
+    pub pa: i16,
+    pub pb: i16,
+    pub pc: i16,
+    pub pd: i16,
+}
+
+// points in a scanline where something visible to the game happens
+#[derive(Clone, Copy, PartialEq)]
+pub enum PpuEvent {
+    // the visible part of the line is done, `line` can be drawn
+    HBlank { line: u16 },
+    // vcount has moved on to `line`
+    LineStart { line: u16 },
+}
+
+// the timing side of the video hardware. each of the 228 scanlines takes
+// 1232 cycles, 960 drawing the 240 visible pixels and 272 in hblank. lines
+// 160-227 are vblank
+pub struct Ppu {
+    pub vcount: u16,
+    // cycles into the current line
+    pub dot: u32,
+    pub frame: u64,
+    // 15 bit colours, row by row
+    pub framebuffer: Box<[u16]>,
+    // bg2 and bg3
+    pub affine: [AffineState; 2],
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu {
+            vcount: 0,
+            dot: 0,
+            frame: 0,
+            framebuffer: vec![0; lcd::WIDTH * lcd::HEIGHT].into_boxed_slice(),
+            affine: [AffineState::default(); 2],
+        }
+    }
+
+    // runs for at most `cycles`, stopping early at the next event so the
+    // caller can act on it before going on. returns the cycles used
+    pub fn advance(&mut self, cycles: u32) -> (u32, Option<PpuEvent>) {
+        let step = cycles.min(self.cycles_to_event());
+        self.dot += step;
+
+        if self.dot == lcd::HDRAW_CYCLES {
+            return (step, Some(PpuEvent::HBlank { line: self.vcount }));
+        }
+
+        if self.dot == lcd::LINE_CYCLES {
+            self.dot = 0;
+            self.vcount = (self.vcount + 1) % lcd::LINES;
+            if self.vcount == lcd::HEIGHT as u16 {
+                self.frame += 1;
+            }
+            return (step, Some(PpuEvent::LineStart { line: self.vcount }));
+        }
+
+        (step, None)
+    }
+
+    // how long until advance would next stop at an event
+    pub fn cycles_to_event(&self) -> u32 {
+        if self.dot < lcd::HDRAW_CYCLES { lcd::HDRAW_CYCLES - self.dot } else { lcd::LINE_CYCLES - self.dot }
+    }
+
+    pub fn in_vblank(&self) -> bool {
+        self.vcount >= lcd::HEIGHT as u16
+    }
+
+    // the read only low bits of DISPSTAT. the vblank flag drops on the last
+    // line, a line before vcount wraps back to 0
+    pub fn status(&self, vcount_setting: u8) -> u8 {
+        let mut status = 0;
+
+        if self.in_vblank() && self.vcount != lcd::LINES - 1 {
+            status |= dispstat::VBLANK;
+        }
+        if self.dot >= lcd::HDRAW_CYCLES {
+            status |= dispstat::HBLANK;
+        }
+        if self.vcount == vcount_setting as u16 {
+            status |= dispstat::VCOUNT_MATCH;
+        }
+
+        status
+    }
+
+    // reloads the reference point of bg2 (0) or bg3 (1) from its registers,
+    // as happens every vblank
+    pub fn latch_affine(&mut self, background: usize, ram: &[u8]) {
+        self.latch_affine_x(background, ram);
+        self.latch_affine_y(background, ram);
+    }
+
+    // a write to one of the registers only reloads that one, so the other
+    // carries on from where the lines so far have moved it
+    pub fn latch_affine_x(&mut self, background: usize, ram: &[u8]) {
+        let addr = if background == 0 { io_address::BG2X } else { io_address::BG3X };
+        self.affine[background].x = reference(ram, addr);
+    }
+
+    pub fn latch_affine_y(&mut self, background: usize, ram: &[u8]) {
+        let addr = if background == 0 { io_address::BG2Y } else { io_address::BG3Y };
+        self.affine[background].y = reference(ram, addr);
+    }
+
+    // draws a visible line into the framebuffer, at the start of its hblank
+    pub fn render_line(&mut self, line: u16, ram: &[u8]) {
+        let control = io16(ram, io_address::DISPCNT);
+        let start = line as usize * lcd::WIDTH;
+
+        for (i, &params) in [io_address::BG2PA, io_address::BG3PA].iter().enumerate() {
+            let affine = &mut self.affine[i];
+            affine.pa = io16(ram, params) as i16;
+            affine.pb = io16(ram, params + 2) as i16;
+            affine.pc = io16(ram, params + 4) as i16;
+            affine.pd = io16(ram, params + 6) as i16;
+        }
+
+        if (control & dispcnt::FORCED_BLANK) != 0 {
+            self.framebuffer[start..start + lcd::WIDTH].fill(BLANK_COLOR);
+        } else {
+            let mosaic = Mosaic::read(ram);
+            let layers = self.render_backgrounds(control, line, ram, &mosaic);
+            let mut objects = ObjLine::new();
+            if (control & dispcnt::OBJ) != 0 {
+                sprite::render_line(control, line, ram, &mosaic, &mut objects);
+            }
+
+            let enables = effects::window_enables(control, line, ram, &objects);
+            let blend = Blend::read(ram);
+            let backdrop = io16(ram, memory_region::PALETTE);
+            let priority = |background: usize| io16(ram, io_address::BG0CNT + background * 2) & bgcnt::PRIORITY_MASK;
+
+            let row = &mut self.framebuffer[start..start + lcd::WIDTH];
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let enable = enables[x];
+
+                // the two frontmost layers, by priority and then with
+                // sprites ahead of backgrounds and lower numbered
+                // backgrounds ahead of higher. the backdrop is behind all
+                let mut top = Candidate { priority: 4, rank: 5, layer: effects::BACKDROP_LAYER, color: backdrop };
+                let mut below = None;
+
+                let object = objects.pixels[x].filter(|_| (enable & (1 << effects::OBJ_LAYER)) != 0);
+                let visible_objects = object.map(|object| Candidate { priority: object.priority, rank: 0, layer: effects::OBJ_LAYER, color: object.color });
+                let visible_backgrounds = (0..4)
+                    .filter(|&background| (enable & (1 << background)) != 0)
+                    .filter_map(|background| {
+                        let color = layers[background].as_ref().and_then(|layer| layer[x])?;
+                        Some(Candidate { priority: priority(background), rank: background + 1, layer: background, color })
+                    });
+
+                for candidate in visible_objects.into_iter().chain(visible_backgrounds) {
+                    if candidate.key() < top.key() {
+                        below = Some(top);
+                        top = candidate;
+                    } else if below.is_none_or(|below: Candidate| candidate.key() < below.key()) {
+                        below = Some(candidate);
+                    }
+                }
+
+                let color = if (enable & effects::EFFECTS_ENABLE) != 0 {
+                    let semi_transparent = top.layer == effects::OBJ_LAYER && object.is_some_and(|object| object.semi_transparent);
+                    blend.apply((top.layer, top.color), below.map(|below| (below.layer, below.color)), semi_transparent)
+                } else {
+                    top.color
+                };
+
+                *pixel = color & 0x7FFF;
+            }
+        }
+
+        for affine in self.affine.iter_mut() {
+            affine.x += affine.pb as i32;
+            affine.y += affine.pd as i32;
+        }
+    }
+
+    // which backgrounds exist and what kind they are depends on the mode:
+    // 0 has four text layers, 1 has two text and an affine bg2, 2 has
+    // affine bg2 and bg3 and the bitmap modes only bg2
+    fn render_backgrounds(&self, control: u16, line: u16, ram: &[u8], mosaic: &Mosaic) -> [Option<Layer>; 4] {
+        let mode = control & dispcnt::MODE_MASK;
+        let mut layers: [Option<Layer>; 4] = [None; 4];
+
+        for (background, slot) in layers.iter_mut().enumerate() {
+            if (control & (dispcnt::BG0 << background)) == 0 {
+                continue;
+            }
+
+            // vertical mosaic repeats the first line of each block, which
+            // for affine layers means backing the reference point up to it
+            let mosaic_enabled = (io16(ram, io_address::BG0CNT + background * 2) & bgcnt::MOSAIC) != 0;
+            let repeat = if mosaic_enabled { line as usize % mosaic.bg_v } else { 0 };
+            let mut affine = self.affine[background.saturating_sub(2).min(1)];
+            affine.x -= affine.pb as i32 * repeat as i32;
+            affine.y -= affine.pd as i32 * repeat as i32;
+
+            let mut layer: Layer = [None; lcd::WIDTH];
+            match (mode, background) {
+                (0, _) | (1, 0) | (1, 1) => tiled::text_line(background, line - repeat as u16, ram, &mut layer),
+                (1, 2) | (2, 2) | (2, 3) => tiled::affine_line(background, &affine, ram, &mut layer),
+                (3..=5, 2) => bitmap::render_line(mode, control, &affine, ram, &mut layer),
+                _ => continue,
+            }
+
+            if mosaic_enabled && mosaic.bg_h > 1 {
+                for x in 0..lcd::WIDTH {
+                    layer[x] = layer[x - x % mosaic.bg_h];
+                }
+            }
+
+            *slot = Some(layer);
+        }
+
+        layers
+    }
+}
+
+pub fn io16(ram: &[u8], addr: usize) -> u16 {
+    u16::from_le_bytes([ram[addr], ram[addr + 1]])
+}
+
+pub fn io32(ram: &[u8], addr: usize) -> u32 {
+    u32::from_le_bytes([ram[addr], ram[addr + 1], ram[addr + 2], ram[addr + 3]])
+}
+
+// the reference point registers are 28 bit signed fixed point with 8
+// fractional bits
+fn reference(ram: &[u8], addr: usize) -> i32 {
+    ((io32(ram, addr) << 4) as i32) >> 4
+}
+
+// widens a 15 bit colour to 8 bits a channel, copying the top bits down so
+// full intensity comes out as 255
+pub fn rgb888(color: u16) -> [u8; 3] {
+    let expand = |channel: u16| ((channel << 3) | (channel >> 2)) as u8;
+    [expand(color & 0x1F), expand((color >> 5) & 0x1F), expand((color >> 10) & 0x1F)]
+}
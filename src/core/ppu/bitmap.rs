@@ -0,0 +1,43 @@
+use crate::core::ppu::Layer;
+use crate::core::ppu::AffineState;
+use crate::core::ppu::io16;
+use crate::constants::dispcnt;
+use crate::constants::memory_region;
+use crate::constants::lcd;
+
+// the second page of modes 4 and 5 starts 40 KiB into vram
+const PAGE_SIZE: usize = 0xA000;
+
+// the bitmap modes draw bg2 straight from vram. it is always an affine
+// layer, so the picture can be scaled and rotated, and anything outside the
+// bitmap is left transparent rather than wrapping
+pub fn render_line(mode: u16, control: u16, affine: &AffineState, ram: &[u8], layer: &mut Layer) {
+    let (width, height) = match mode {
+        5 => (160, 128),
+        _ => (lcd::WIDTH as i32, lcd::HEIGHT as i32),
+    };
+
+    let page = if mode != 3 && (control & dispcnt::FRAME_SELECT) != 0 { PAGE_SIZE } else { 0 };
+    let base = memory_region::VRAM + page;
+
+    let pa = affine.pa as i32;
+    let pc = affine.pc as i32;
+
+    for (i, pixel) in layer.iter_mut().enumerate() {
+        let x = (affine.x + pa * i as i32) >> 8;
+        let y = (affine.y + pc * i as i32) >> 8;
+
+        if x < 0 || y < 0 || x >= width || y >= height {
+            continue;
+        }
+
+        let offset = (y * width + x) as usize;
+        *pixel = match mode {
+            4 => {
+                let index = ram[base + offset] as usize;
+                if index == 0 { None } else { Some(io16(ram, memory_region::PALETTE + index * 2)) }
+            },
+            _ => Some(io16(ram, base + offset * 2)),
+        };
+    }
+}
@@ -0,0 +1,163 @@
+use crate::core::ppu::io16;
+use crate::core::ppu::sprite::ObjLine;
+use crate::constants::dispcnt;
+use crate::constants::io_address;
+use crate::constants::lcd;
+
+// layer numbers as used by the window and blend control bits
+pub const OBJ_LAYER: usize = 4;
+pub const BACKDROP_LAYER: usize = 5;
+// the window bit that lets blending and fades happen
+pub const EFFECTS_ENABLE: u8 = 1 << 5;
+const ALL_ENABLED: u8 = 0x3F;
+
+#[derive(Clone, Copy, PartialEq)]
+enum BlendMode {
+    Off,
+    Alpha,
+    Brighten,
+    Darken,
+}
+
+// the block sizes in pixels, one more than the fields of MOSAIC
+#[derive(Clone, Copy)]
+pub struct Mosaic {
+    pub bg_h: usize,
+    pub bg_v: usize,
+    pub obj_h: usize,
+    pub obj_v: usize,
+}
+
+impl Mosaic {
+    pub fn read(ram: &[u8]) -> Mosaic {
+        let mosaic = io16(ram, io_address::MOSAIC) as usize;
+        Mosaic {
+            bg_h: (mosaic & 0xF) + 1,
+            bg_v: ((mosaic >> 4) & 0xF) + 1,
+            obj_h: ((mosaic >> 8) & 0xF) + 1,
+            obj_v: ((mosaic >> 12) & 0xF) + 1,
+        }
+    }
+}
+
+// which layers and effects each pixel of the line shows. win0 beats win1,
+// which beats the obj window, and everything else is outside. with no
+// window switched on everything is shown everywhere
+pub fn window_enables(control: u16, line: u16, ram: &[u8], objects: &ObjLine) -> [u8; lcd::WIDTH] {
+    if (control & (dispcnt::WIN0 | dispcnt::WIN1 | dispcnt::OBJ_WIN)) == 0 {
+        return [ALL_ENABLED; lcd::WIDTH];
+    }
+
+    let inside = io16(ram, io_address::WININ);
+    let outside = io16(ram, io_address::WINOUT);
+    let mut enables = [(outside & 0x3F) as u8; lcd::WIDTH];
+
+    for (x, enable) in enables.iter_mut().enumerate() {
+        if (control & dispcnt::OBJ_WIN) != 0 && objects.window[x] {
+            *enable = ((outside >> 8) & 0x3F) as u8;
+        }
+    }
+
+    // win1 first so win0 ends up on top where they overlap
+    let windows = [(dispcnt::WIN1, io_address::WIN1H, io_address::WIN1V, 8), (dispcnt::WIN0, io_address::WIN0H, io_address::WIN0V, 0)];
+    for (enable_bit, horizontal, vertical, shift) in windows {
+        if (control & enable_bit) == 0 {
+            continue;
+        }
+
+        let (left, right) = window_edges(io16(ram, horizontal), lcd::WIDTH);
+        let (top, bottom) = window_edges(io16(ram, vertical), lcd::HEIGHT);
+        if !within(line as usize, top, bottom) {
+            continue;
+        }
+
+        for (x, enable) in enables.iter_mut().enumerate() {
+            if within(x, left, right) {
+                *enable = ((inside >> shift) & 0x3F) as u8;
+            }
+        }
+    }
+
+    enables
+}
+
+// the high byte is the first edge and the low byte one past the last. an
+// end past the screen or before the start is taken as the screen edge
+fn window_edges(value: u16, limit: usize) -> (usize, usize) {
+    let start = (value >> 8) as usize;
+    let end = (value & 0xFF) as usize;
+    let end = if end > limit || start > end { limit } else { end };
+    (start, end)
+}
+
+fn within(position: usize, start: usize, end: usize) -> bool {
+    position >= start && position < end
+}
+
+// the colour special effects from BLDCNT, BLDALPHA and BLDY
+pub struct Blend {
+    mode: BlendMode,
+    first: u16,
+    second: u16,
+    eva: u16,
+    evb: u16,
+    evy: u16,
+}
+
+impl Blend {
+    pub fn read(ram: &[u8]) -> Blend {
+        let control = io16(ram, io_address::BLDCNT);
+        let alpha = io16(ram, io_address::BLDALPHA);
+
+        Blend {
+            mode: match (control >> 6) & 0x3 {
+                0 => BlendMode::Off,
+                1 => BlendMode::Alpha,
+                2 => BlendMode::Brighten,
+                _ => BlendMode::Darken,
+            },
+            first: control & 0x3F,
+            second: (control >> 8) & 0x3F,
+            // the coefficients are 1.4 fixed point and stop at 1
+            eva: (alpha & 0x1F).min(16),
+            evb: ((alpha >> 8) & 0x1F).min(16),
+            evy: (io16(ram, io_address::BLDY) & 0x1F).min(16),
+        }
+    }
+
+    // the final colour for a pixel from its top two layers. semi-transparent
+    // sprites blend with whatever second target is below them regardless of
+    // the mode, and otherwise fall back to the normal effect
+    pub fn apply(&self, top: (usize, u16), below: Option<(usize, u16)>, semi_transparent: bool) -> u16 {
+        let (top_layer, top_color) = top;
+        let second = below.filter(|&(layer, _)| (self.second & (1 << layer)) != 0);
+
+        if let (true, Some((_, below_color))) = (semi_transparent, second) {
+            return self.alpha(top_color, below_color);
+        }
+
+        if (self.first & (1 << top_layer)) == 0 {
+            return top_color;
+        }
+
+        match (self.mode, second) {
+            (BlendMode::Alpha, Some((_, below_color))) => self.alpha(top_color, below_color),
+            (BlendMode::Brighten, _) => map_channels(top_color, |c| c + (((31 - c) * self.evy) >> 4)),
+            (BlendMode::Darken, _) => map_channels(top_color, |c| c - ((c * self.evy) >> 4)),
+            _ => top_color,
+        }
+    }
+
+    fn alpha(&self, a: u16, b: u16) -> u16 {
+        let mut color = 0;
+        for shift in [0, 5, 10] {
+            let channel = (((a >> shift) & 0x1F) * self.eva + ((b >> shift) & 0x1F) * self.evb) >> 4;
+            color |= channel.min(31) << shift;
+        }
+        color
+    }
+}
+
+fn map_channels(color: u16, f: impl Fn(u16) -> u16) -> u16 {
+    [0, 5, 10].iter().fold(0, |result, &shift| result | (f((color >> shift) & 0x1F) << shift))
+}
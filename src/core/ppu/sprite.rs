@@ -0,0 +1,252 @@
+use crate::core::ppu::io16;
+use crate::core::ppu::effects::Mosaic;
+use crate::constants::dispcnt;
+use crate::constants::memory_region;
+use crate::constants::lcd;
+use crate::constants::OAM_SIZE;
+use crate::constants::OBJ_VRAM;
+use crate::constants::OBJ_PALETTE;
+
+// cycles the sprite unit gets per line, less when hblank is left free for
+// the cpu to get at oam and vram
+const LINE_BUDGET: i32 = 1210;
+const HBLANK_FREE_BUDGET: i32 = 954;
+
+// in the bitmap modes the first half of sprite vram holds the second page
+// of the picture, so tiles 0-511 can't be used
+const BITMAP_FIRST_TILE: usize = 512;
+
+// width and height for each shape (square, wide, tall) and size
+const SIZES: [[(i32, i32); 4]; 3] = [
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+
+mod attribute {
+    pub const AFFINE: u16 = 1 << 8;
+    pub const DOUBLE_SIZE: u16 = 1 << 9;
+    pub const DISABLED: u16 = 1 << 9;
+    pub const MOSAIC: u16 = 1 << 12;
+    pub const COLOR_256: u16 = 1 << 13;
+    pub const H_FLIP: u16 = 1 << 12;
+    pub const V_FLIP: u16 = 1 << 13;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ObjMode {
+    Normal,
+    SemiTransparent,
+    Window,
+}
+
+#[derive(Clone, Copy)]
+pub struct ObjPixel {
+    pub color: u16,
+    pub priority: u16,
+    pub semi_transparent: bool,
+}
+
+// the sprites on one scanline, plus where obj window sprites cover it
+pub struct ObjLine {
+    pub pixels: [Option<ObjPixel>; lcd::WIDTH],
+    pub window: [bool; lcd::WIDTH],
+}
+
+impl ObjLine {
+    pub fn new() -> ObjLine {
+        ObjLine {
+            pixels: [None; lcd::WIDTH],
+            window: [false; lcd::WIDTH],
+        }
+    }
+}
+
+// one oam entry, unpacked
+pub struct Sprite {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub affine: Option<usize>,
+    pub double_size: bool,
+    pub mode: ObjMode,
+    pub mosaic: bool,
+    pub color_256: bool,
+    pub h_flip: bool,
+    pub v_flip: bool,
+    pub tile: usize,
+    pub priority: u16,
+    pub palette: usize,
+}
+
+impl Sprite {
+    pub fn read(ram: &[u8], index: usize) -> Option<Sprite> {
+        let base = memory_region::OAM + index * 8;
+        let attr0 = io16(ram, base);
+        let attr1 = io16(ram, base + 2);
+        let attr2 = io16(ram, base + 4);
+
+        let affine = (attr0 & attribute::AFFINE) != 0;
+        if !affine && (attr0 & attribute::DISABLED) != 0 {
+            return None;
+        }
+
+        let mode = match (attr0 >> 10) & 0x3 {
+            0 => ObjMode::Normal,
+            1 => ObjMode::SemiTransparent,
+            2 => ObjMode::Window,
+            _ => return None,
+        };
+
+        let shape = ((attr0 >> 14) & 0x3) as usize;
+        if shape == 3 {
+            return None;
+        }
+        let (width, height) = SIZES[shape][((attr1 >> 14) & 0x3) as usize];
+
+        // x is nine bits signed, y wraps round at 256
+        let x = ((attr1 & 0x1FF) as i32) << 23 >> 23;
+        let y = (attr0 & 0xFF) as i32;
+
+        Some(Sprite {
+            x,
+            y,
+            width,
+            height,
+            affine: if affine { Some(((attr1 >> 9) & 0x1F) as usize) } else { None },
+            double_size: affine && (attr0 & attribute::DOUBLE_SIZE) != 0,
+            mode,
+            mosaic: (attr0 & attribute::MOSAIC) != 0,
+            color_256: (attr0 & attribute::COLOR_256) != 0,
+            h_flip: !affine && (attr1 & attribute::H_FLIP) != 0,
+            v_flip: !affine && (attr1 & attribute::V_FLIP) != 0,
+            tile: (attr2 & 0x3FF) as usize,
+            priority: (attr2 >> 10) & 0x3,
+            palette: (attr2 >> 12) as usize,
+        })
+    }
+
+    // the area the sprite takes on screen, twice its size when double size
+    // gives a rotated sprite room to turn without being clipped
+    pub fn bounds(&self) -> (i32, i32) {
+        if self.double_size { (self.width * 2, self.height * 2) } else { (self.width, self.height) }
+    }
+
+    // what drawing it on a line costs out of the budget
+    fn cycles(&self) -> i32 {
+        let (bounds_width, _) = self.bounds();
+        if self.affine.is_some() { 10 + bounds_width * 2 } else { self.width }
+    }
+
+    // pa, pb, pc, pd live in the spare fourth halfword of four entries
+    fn affine_params(&self, ram: &[u8]) -> [i32; 4] {
+        match self.affine {
+            Some(group) => {
+                let base = memory_region::OAM + group * 32 + 6;
+                [0, 1, 2, 3].map(|i| io16(ram, base + i * 8) as i16 as i32)
+            },
+            None => [0x100, 0, 0, 0x100],
+        }
+    }
+
+    // palette index at a point in the sprite, 0 for transparent. 2d mapping
+    // lays sprite tiles out in a 32 tile wide sheet, 1d packs each sprite's
+    // rows one after the other. 256 colour tiles take two tile numbers each
+    pub fn pixel(&self, ram: &[u8], one_dimensional: bool, x: i32, y: i32) -> u8 {
+        let (x, y) = (x as usize, y as usize);
+        let tile_step = if self.color_256 { 2 } else { 1 };
+        let row_stride = if one_dimensional { (self.width as usize / 8) * tile_step } else { 32 };
+
+        let tile = (self.tile + (y / 8) * row_stride + (x / 8) * tile_step) & 0x3FF;
+        let offset = tile * 32 + if self.color_256 { (y % 8) * 8 + x % 8 } else { (y % 8) * 4 + (x % 8) / 2 };
+
+        let byte = ram[OBJ_VRAM + (offset & 0x7FFF)];
+        if self.color_256 { byte } else { (byte >> ((x & 1) * 4)) & 0xF }
+    }
+}
+
+// walks oam in order, drawing each sprite on the line until the cycle
+// budget runs out. where sprites overlap the lower priority number wins,
+// then the lower oam index. mosaic sprites are sampled at the top left of
+// each screen space mosaic block, kept inside the sprite
+pub fn render_line(control: u16, line: u16, ram: &[u8], mosaic: &Mosaic, objects: &mut ObjLine) {
+    let one_dimensional = (control & dispcnt::OBJ_1D) != 0;
+    let bitmap_mode = (control & dispcnt::MODE_MASK) >= 3;
+    let mut budget = if (control & dispcnt::HBLANK_FREE) != 0 { HBLANK_FREE_BUDGET } else { LINE_BUDGET };
+
+    for index in 0..OAM_SIZE / 8 {
+        let sprite = match Sprite::read(ram, index) {
+            Some(sprite) => sprite,
+            None => continue,
+        };
+
+        let (bounds_width, bounds_height) = sprite.bounds();
+        let sprite_y = (line as i32 - sprite.y) & 0xFF;
+        if sprite_y >= bounds_height {
+            continue;
+        }
+
+        budget -= sprite.cycles();
+        if budget < 0 {
+            break;
+        }
+
+        if bitmap_mode && sprite.tile < BITMAP_FIRST_TILE {
+            continue;
+        }
+
+        let sprite_y = if sprite.mosaic { (sprite_y - (line as usize % mosaic.obj_v) as i32).max(0) } else { sprite_y };
+
+        let [pa, pb, pc, pd] = sprite.affine_params(ram);
+        let half_width = bounds_width / 2;
+        let half_height = bounds_height / 2;
+
+        for bounds_x in 0..bounds_width {
+            let screen_x = sprite.x + bounds_x;
+            if screen_x < 0 || screen_x >= lcd::WIDTH as i32 {
+                continue;
+            }
+
+            let bounds_x = if sprite.mosaic { (bounds_x - screen_x % mosaic.obj_h as i32).max(0) } else { bounds_x };
+
+            // affine sprites rotate about their centre, plain ones just
+            // read straight across with the flips applied
+            let (x, y) = if sprite.affine.is_some() {
+                let dx = bounds_x - half_width;
+                let dy = sprite_y - half_height;
+                (((pa * dx + pb * dy) >> 8) + sprite.width / 2, ((pc * dx + pd * dy) >> 8) + sprite.height / 2)
+            } else {
+                let x = if sprite.h_flip { sprite.width - 1 - bounds_x } else { bounds_x };
+                let y = if sprite.v_flip { sprite.height - 1 - sprite_y } else { sprite_y };
+                (x, y)
+            };
+
+            if x < 0 || y < 0 || x >= sprite.width || y >= sprite.height {
+                continue;
+            }
+
+            let index = sprite.pixel(ram, one_dimensional, x, y) as usize;
+            if index == 0 {
+                continue;
+            }
+
+            let screen_x = screen_x as usize;
+            if sprite.mode == ObjMode::Window {
+                objects.window[screen_x] = true;
+                continue;
+            }
+
+            if matches!(objects.pixels[screen_x], Some(pixel) if pixel.priority <= sprite.priority) {
+                continue;
+            }
+
+            let palette_index = if sprite.color_256 { index } else { sprite.palette * 16 + index };
+            objects.pixels[screen_x] = Some(ObjPixel {
+                color: io16(ram, OBJ_PALETTE + palette_index * 2),
+                priority: sprite.priority,
+                semi_transparent: sprite.mode == ObjMode::SemiTransparent,
+            });
+        }
+    }
+}
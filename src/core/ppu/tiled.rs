@@ -0,0 +1,121 @@
+use crate::core::ppu::Layer;
+use crate::core::ppu::AffineState;
+use crate::core::ppu::io16;
+use crate::constants::bgcnt;
+use crate::constants::io_address;
+use crate::constants::memory_region;
+
+// backgrounds can only take tiles from the first 64 KiB of vram, the rest
+// belongs to sprites
+const BG_VRAM_SIZE: usize = 0x1_0000;
+const CHAR_BLOCK_SIZE: usize = 0x4000;
+const SCREEN_BLOCK_SIZE: usize = 0x800;
+
+// where a background's tiles and map start in vram
+fn bases(control: u16) -> (usize, usize) {
+    let char_base = ((control >> 2) & 0x3) as usize * CHAR_BLOCK_SIZE;
+    let screen_base = ((control >> 8) & 0x1F) as usize * SCREEN_BLOCK_SIZE;
+    (char_base, screen_base)
+}
+
+// one pixel of a tile as a palette index, 0 being transparent. rows of 4bpp
+// tiles are 4 bytes with the left pixel in the low nibble, 8bpp rows are 8
+pub fn tile_pixel(ram: &[u8], char_base: usize, tile: usize, x: usize, y: usize, color_256: bool) -> u8 {
+    let (tile_size, row_size) = if color_256 { (64, 8) } else { (32, 4) };
+    let offset = char_base + tile * tile_size + y * row_size + if color_256 { x } else { x / 2 };
+
+    if offset >= BG_VRAM_SIZE {
+        return 0;
+    }
+
+    let byte = ram[memory_region::VRAM + offset];
+    if color_256 { byte } else { (byte >> ((x & 1) * 4)) & 0xF }
+}
+
+// text backgrounds are built from 32x32 tile screen blocks, laid side by
+// side for the wide sizes and one above the other for the tall ones. each
+// map entry picks a tile, its flips and for 4bpp its palette bank
+pub fn text_line(background: usize, line: u16, ram: &[u8], layer: &mut Layer) {
+    let control = io16(ram, io_address::BG0CNT + background * 2);
+    let h_offset = (io16(ram, io_address::BG0HOFS + background * 4) & 0x1FF) as usize;
+    let v_offset = (io16(ram, io_address::BG0HOFS + background * 4 + 2) & 0x1FF) as usize;
+
+    let (width, height) = text_size(control);
+    let y = (line as usize + v_offset) % height;
+
+    for (i, pixel) in layer.iter_mut().enumerate() {
+        *pixel = text_pixel(ram, control, (i + h_offset) % width, y);
+    }
+}
+
+// the whole map is 256 or 512 pixels each way
+pub fn text_size(control: u16) -> (usize, usize) {
+    let size = (control >> 14) & 0x3;
+    let width = if size & 1 != 0 { 512 } else { 256 };
+    let height = if size & 2 != 0 { 512 } else { 256 };
+    (width, height)
+}
+
+// the colour at a point on a text background's map, None if transparent
+pub fn text_pixel(ram: &[u8], control: u16, x: usize, y: usize) -> Option<u16> {
+    let (char_base, screen_base) = bases(control);
+    let color_256 = (control & bgcnt::COLOR_256) != 0;
+    let (width, _) = text_size(control);
+
+    // the block to the right is always next, the one below comes after
+    // however many blocks make up a row
+    let block = (x / 256) + (y / 256) * (width / 256);
+    let entry_addr = screen_base + block * SCREEN_BLOCK_SIZE + ((y % 256) / 8 * 32 + (x % 256) / 8) * 2;
+    let entry = io16(ram, memory_region::VRAM + (entry_addr % BG_VRAM_SIZE));
+
+    let tile = (entry & 0x3FF) as usize;
+    let tile_x = if (entry & (1 << 10)) != 0 { 7 - x % 8 } else { x % 8 };
+    let tile_y = if (entry & (1 << 11)) != 0 { 7 - y % 8 } else { y % 8 };
+
+    let index = tile_pixel(ram, char_base, tile, tile_x, tile_y, color_256) as usize;
+    if index == 0 {
+        return None;
+    }
+
+    let palette_index = if color_256 { index } else { ((entry >> 12) as usize) * 16 + index };
+    Some(io16(ram, memory_region::PALETTE + palette_index * 2))
+}
+
+// affine backgrounds are square maps of one byte tile numbers, always
+// 256 colour. off the edge of the map is either transparent or wraps back
+// round, depending on the wraparound bit
+pub fn affine_line(background: usize, affine: &AffineState, ram: &[u8], layer: &mut Layer) {
+    let control = io16(ram, io_address::BG0CNT + background * 2);
+    let size = affine_size(control) as i32;
+    let wraparound = (control & bgcnt::WRAPAROUND) != 0;
+
+    for (i, pixel) in layer.iter_mut().enumerate() {
+        let mut x = (affine.x + affine.pa as i32 * i as i32) >> 8;
+        let mut y = (affine.y + affine.pc as i32 * i as i32) >> 8;
+
+        if wraparound {
+            x = x.rem_euclid(size);
+            y = y.rem_euclid(size);
+        } else if x < 0 || y < 0 || x >= size || y >= size {
+            continue;
+        }
+
+        *pixel = affine_pixel(ram, control, x as usize, y as usize);
+    }
+}
+
+// 128, 256, 512 or 1024 pixels square
+pub fn affine_size(control: u16) -> usize {
+    128 << ((control >> 14) & 0x3)
+}
+
+pub fn affine_pixel(ram: &[u8], control: u16, x: usize, y: usize) -> Option<u16> {
+    let (char_base, screen_base) = bases(control);
+    let size = affine_size(control);
+
+    let entry_addr = screen_base + (y / 8) * (size / 8) + x / 8;
+    let tile = ram[memory_region::VRAM + (entry_addr % BG_VRAM_SIZE)] as usize;
+
+    let index = tile_pixel(ram, char_base, tile, x % 8, y % 8, true) as usize;
+    if index == 0 { None } else { Some(io16(ram, memory_region::PALETTE + index * 2)) }
+}
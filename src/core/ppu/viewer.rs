@@ -0,0 +1,221 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::ppu::io16;
+use crate::core::ppu::sprite::ObjMode;
+use crate::core::ppu::sprite::Sprite;
+use crate::core::ppu::tiled;
+use crate::core::screenshot;
+use crate::core::screenshot::ImageFormat;
+use crate::constants::dispcnt;
+use crate::constants::io_address;
+use crate::constants::memory_region;
+use crate::constants::OAM_SIZE;
+use crate::constants::OBJ_PALETTE;
+
+// what a transparent sprite pixel shows as, so it stands out
+const TRANSPARENT: u16 = 0x7C1F;
+const SWATCH_SIZE: usize = 8;
+const CHAR_BLOCK_SIZE: usize = 0x4000;
+// blocks 4 and 5 are sprite tiles
+pub const CHAR_BLOCKS: usize = 6;
+pub const SPRITES: usize = OAM_SIZE / 8;
+
+// a picture of some part of video memory, in 15 bit colours
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u16>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Image {
+        Image { width, height, pixels: vec![0; width * height] }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: u16) {
+        self.pixels[y * self.width + x] = color & 0x7FFF;
+    }
+}
+
+// all 512 colours as 8x8 squares, 16 to a row. the background palette is
+// the top half and the sprite palette the bottom
+pub fn palette(ram: &[u8]) -> Image {
+    let mut image = Image::new(16 * SWATCH_SIZE, 32 * SWATCH_SIZE);
+
+    for index in 0..512 {
+        let color = io16(ram, memory_region::PALETTE + index * 2);
+        let (left, top) = ((index % 16) * SWATCH_SIZE, (index / 16) * SWATCH_SIZE);
+
+        for y in top..top + SWATCH_SIZE {
+            for x in left..left + SWATCH_SIZE {
+                image.set(x, y, color);
+            }
+        }
+    }
+
+    image
+}
+
+// a 16 KiB character block as a sheet of tiles, 32 tiles across in the
+// order they are numbered. 4bpp tiles are drawn with the given palette
+// bank, from the sprite palette for blocks 4 and 5
+pub fn tile_sheet(ram: &[u8], block: usize, color_256: bool, palette_bank: usize) -> Image {
+    let (tile_size, row_size) = if color_256 { (64, 8) } else { (32, 4) };
+    let tiles = CHAR_BLOCK_SIZE / tile_size;
+    let palette = if block >= 4 { OBJ_PALETTE } else { memory_region::PALETTE };
+    let base = memory_region::VRAM + block * CHAR_BLOCK_SIZE;
+
+    let mut image = Image::new(32 * 8, tiles / 32 * 8);
+
+    for tile in 0..tiles {
+        let (left, top) = ((tile % 32) * 8, (tile / 32) * 8);
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let byte = ram[base + tile * tile_size + y * row_size + if color_256 { x } else { x / 2 }];
+                let index = if color_256 { byte as usize } else { ((byte >> ((x & 1) * 4)) & 0xF) as usize + (palette_bank & 0xF) * 16 };
+                image.set(left + x, top + y, io16(ram, palette + index * 2));
+            }
+        }
+    }
+
+    image
+}
+
+// a background's whole map as the current mode and its control register
+// lay it out, with the backdrop where it's transparent. None if the mode
+// doesn't have that background
+pub fn background(ram: &[u8], background: usize) -> Option<Image> {
+    let control = io16(ram, io_address::DISPCNT);
+    let mode = control & dispcnt::MODE_MASK;
+    let bg_control = io16(ram, io_address::BG0CNT + background * 2);
+    let backdrop = io16(ram, memory_region::PALETTE);
+
+    let page = if mode != 3 && (control & dispcnt::FRAME_SELECT) != 0 { 0xA000 } else { 0 };
+    let bitmap_base = memory_region::VRAM + page;
+
+    let (width, height) = match (mode, background) {
+        (0, _) | (1, 0) | (1, 1) => tiled::text_size(bg_control),
+        (1, 2) | (2, 2) | (2, 3) => (tiled::affine_size(bg_control), tiled::affine_size(bg_control)),
+        (5, 2) => (160, 128),
+        (3 | 4, 2) => (240, 160),
+        _ => return None,
+    };
+
+    let pixel = |x: usize, y: usize| match mode {
+        0 | 1 if background < 2 || mode == 0 => tiled::text_pixel(ram, bg_control, x, y),
+        1 | 2 => tiled::affine_pixel(ram, bg_control, x, y),
+        4 => match ram[bitmap_base + y * width + x] as usize {
+            0 => None,
+            index => Some(io16(ram, memory_region::PALETTE + index * 2)),
+        },
+        _ => Some(io16(ram, bitmap_base + (y * width + x) * 2)),
+    };
+
+    let mut image = Image::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            image.set(x, y, pixel(x, y).unwrap_or(backdrop));
+        }
+    }
+
+    Some(image)
+}
+
+// one oam entry drawn at its own size without its flips or rotation,
+// transparent pixels in magenta, and a line describing its attributes.
+// disabled entries only get the description
+pub fn sprite(ram: &[u8], index: usize) -> (Option<Image>, String) {
+    let sprite = match Sprite::read(ram, index) {
+        Some(sprite) => sprite,
+        None => return (None, format!("obj {:3}: disabled", index)),
+    };
+
+    let one_dimensional = (io16(ram, io_address::DISPCNT) & dispcnt::OBJ_1D) != 0;
+    let mut image = Image::new(sprite.width as usize, sprite.height as usize);
+
+    for y in 0..sprite.height {
+        for x in 0..sprite.width {
+            let index = sprite.pixel(ram, one_dimensional, x, y) as usize;
+            let color = match index {
+                0 => TRANSPARENT,
+                _ if sprite.color_256 => io16(ram, OBJ_PALETTE + index * 2),
+                _ => io16(ram, OBJ_PALETTE + (sprite.palette * 16 + index) * 2),
+            };
+            image.set(x as usize, y as usize, color);
+        }
+    }
+
+    (Some(image), describe(index, &sprite))
+}
+
+fn describe(index: usize, sprite: &Sprite) -> String {
+    let mut text = format!(
+        "obj {:3}: x {} y {} {}x{} {} tile {} priority {}",
+        index, sprite.x, sprite.y, sprite.width, sprite.height,
+        if sprite.color_256 { "8bpp" } else { "4bpp" }, sprite.tile, sprite.priority,
+    );
+
+    if !sprite.color_256 {
+        let _ = write!(text, " palette {}", sprite.palette);
+    }
+    match sprite.mode {
+        ObjMode::Normal => {},
+        ObjMode::SemiTransparent => text.push_str(" semi-transparent"),
+        ObjMode::Window => text.push_str(" window"),
+    }
+    if let Some(group) = sprite.affine {
+        let _ = write!(text, " affine {}", group);
+    }
+    if sprite.double_size {
+        text.push_str(" double-size");
+    }
+    if sprite.h_flip {
+        text.push_str(" h-flip");
+    }
+    if sprite.v_flip {
+        text.push_str(" v-flip");
+    }
+    if sprite.mosaic {
+        text.push_str(" mosaic");
+    }
+
+    text
+}
+
+// everything above as files in a directory: palette.png, a 4bpp and an
+// 8bpp sheet per character block, bg0-3.png for the backgrounds the mode
+// has, objNNN.png per sprite and oam.txt listing them all
+pub fn dump(ram: &[u8], dir: &Path, palette_bank: usize) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let save = |name: String, image: &Image| screenshot::save(&dir.join(name), &image.pixels, image.width, image.height, ImageFormat::Png);
+
+    save("palette.png".to_string(), &palette(ram))?;
+
+    for block in 0..CHAR_BLOCKS {
+        save(format!("tiles{}_4bpp.png", block), &tile_sheet(ram, block, false, palette_bank))?;
+        save(format!("tiles{}_8bpp.png", block), &tile_sheet(ram, block, true, palette_bank))?;
+    }
+
+    for index in 0..4 {
+        if let Some(image) = background(ram, index) {
+            save(format!("bg{}.png", index), &image)?;
+        }
+    }
+
+    let mut oam = String::new();
+    for index in 0..SPRITES {
+        let (image, description) = sprite(ram, index);
+        if let Some(image) = image {
+            save(format!("obj{:03}.png", index), &image)?;
+        }
+        oam.push_str(&description);
+        oam.push('\n');
+    }
+
+    fs::write(dir.join("oam.txt"), oam)
+}
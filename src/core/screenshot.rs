@@ -0,0 +1,68 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::ppu::rgb888;
+use crate::png;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    // three bytes a pixel, red first
+    Rgb888,
+    // the console's own little endian 15 bit colours
+    Rgb555,
+}
+
+impl ImageFormat {
+    pub fn parse(name: &str) -> Option<ImageFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "rgb" | "rgb888" => Some(ImageFormat::Rgb888),
+            "rgb555" => Some(ImageFormat::Rgb555),
+            _ => None,
+        }
+    }
+
+    // picks the format from the file extension, png if there isn't one
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        match path.extension() {
+            Some(extension) => ImageFormat::parse(&extension.to_string_lossy()),
+            None => Some(ImageFormat::Png),
+        }
+    }
+}
+
+// turns a buffer of 15 bit colours into the bytes of an image file
+pub fn encode(pixels: &[u16], width: usize, height: usize, format: ImageFormat) -> Vec<u8> {
+    match format {
+        ImageFormat::Rgb555 => pixels.iter().flat_map(|pixel| (pixel & 0x7FFF).to_le_bytes()).collect(),
+        _ => encode_rgb(&to_rgb888(pixels), width, height, format),
+    }
+}
+
+// the same from 8 bit rgb, such as colour corrected output
+pub fn encode_rgb(rgb: &[u8], width: usize, height: usize, format: ImageFormat) -> Vec<u8> {
+    match format {
+        ImageFormat::Png => png::encode(width, height, rgb),
+        ImageFormat::Rgb888 => rgb.to_vec(),
+        ImageFormat::Rgb555 => rgb.chunks(3)
+            .flat_map(|pixel| {
+                let [r, g, b] = [pixel[0] >> 3, pixel[1] >> 3, pixel[2] >> 3].map(|channel| channel as u16);
+                (r | (g << 5) | (b << 10)).to_le_bytes()
+            })
+            .collect(),
+    }
+}
+
+pub fn save(path: &Path, pixels: &[u16], width: usize, height: usize, format: ImageFormat) -> io::Result<()> {
+    fs::write(path, encode(pixels, width, height, format))
+}
+
+pub fn save_rgb(path: &Path, rgb: &[u8], width: usize, height: usize, format: ImageFormat) -> io::Result<()> {
+    fs::write(path, encode_rgb(rgb, width, height, format))
+}
+
+fn to_rgb888(pixels: &[u16]) -> Vec<u8> {
+    pixels.iter().flat_map(|&pixel| rgb888(pixel)).collect()
+}
@@ -0,0 +1,45 @@
+// names for addresses, taken from the symbol table of an elf so that
+// debugging output can say where in the program it is
+#[derive(Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u32,
+    pub size: u32,
+}
+
+#[derive(Clone, Default)]
+pub struct SymbolTable {
+    // sorted by address so lookups can binary search
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new(mut symbols: Vec<Symbol>) -> SymbolTable {
+        symbols.sort_by(|a, b| a.addr.cmp(&b.addr).then_with(|| a.name.cmp(&b.name)));
+        symbols.dedup_by(|a, b| a.addr == b.addr && a.name == b.name);
+        SymbolTable { symbols }
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    // the symbol covering the address and how far into it the address is.
+    // symbols without a size only match their own address
+    pub fn lookup(&self, addr: u32) -> Option<(&Symbol, u32)> {
+        let index = self.symbols.partition_point(|symbol| symbol.addr <= addr);
+
+        self.symbols[..index].iter().rev()
+            .find(|symbol| addr - symbol.addr < symbol.size.max(1))
+            .map(|symbol| (symbol, addr - symbol.addr))
+    }
+
+    // "name", "name+0x1c" or the bare address when nothing covers it
+    pub fn label(&self, addr: u32) -> String {
+        match self.lookup(addr) {
+            Some((symbol, 0)) => symbol.name.clone(),
+            Some((symbol, offset)) => format!("{}+{:#x}", symbol.name, offset),
+            None => format!("{:#010x}", addr),
+        }
+    }
+}
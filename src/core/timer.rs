@@ -0,0 +1,193 @@
+use crate::constants::io_address;
+use crate::constants::tmcnt;
+
+// the prescaler settings, as shifts of the system clock
+const PRESCALER_SHIFTS: [u32; 4] = [0, 6, 8, 10];
+// the registers of one timer, TMxCNT_L then TMxCNT_H
+const TIMER_STRIDE: usize = 4;
+
+// a 16 bit counter that reloads when it overflows. rather than being
+// stepped every cycle, a running timer remembers its count at some moment
+// and works out the current one from how long it's been since
+#[derive(Clone, Copy, Default)]
+struct Timer {
+    reload: u16,
+    control: u8,
+    // the count as of `since`
+    counter: u16,
+    since: u64,
+}
+
+impl Timer {
+    fn enabled(&self) -> bool {
+        (self.control & tmcnt::ENABLE) != 0
+    }
+
+    fn shift(&self) -> u32 {
+        PRESCALER_SHIFTS[(self.control & tmcnt::PRESCALER_MASK) as usize]
+    }
+
+    // counting the system clock rather than the timer below
+    fn clocked(&self, cascade_allowed: bool) -> bool {
+        self.enabled() && !(cascade_allowed && (self.control & tmcnt::CASCADE) != 0)
+    }
+
+    fn counter(&self, now: u64, clocked: bool) -> u16 {
+        if clocked {
+            self.counter.wrapping_add(((now - self.since) >> self.shift()) as u16)
+        } else {
+            self.counter
+        }
+    }
+
+    fn next_overflow(&self) -> u64 {
+        self.since + ((0x1_0000 - self.counter as u64) << self.shift())
+    }
+}
+
+pub struct Timers {
+    timers: [Timer; 4],
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        Timers { timers: [Timer::default(); 4] }
+    }
+
+    // timer 0 has nothing below it to cascade from
+    fn clocked(&self, index: usize) -> bool {
+        self.timers[index].clocked(index > 0)
+    }
+
+    // how long until the first running timer overflows, so the caller can
+    // stop there and act on it
+    pub fn cycles_to_overflow(&self, now: u64) -> u32 {
+        (0..4)
+            .filter(|&index| self.clocked(index))
+            .map(|index| (self.timers[index].next_overflow() - now).min(u32::MAX as u64) as u32)
+            .min()
+            .unwrap_or(u32::MAX)
+    }
+
+    // reloads every timer that has overflowed by `now`, passing overflows
+    // on to cascaded timers. returns the timers that overflowed in order
+    pub fn overflows(&mut self, now: u64) -> Vec<usize> {
+        let mut overflowed = Vec::new();
+
+        for index in 0..4 {
+            if !self.clocked(index) {
+                continue;
+            }
+
+            while self.timers[index].next_overflow() <= now {
+                let timer = &mut self.timers[index];
+                timer.since = timer.next_overflow();
+                timer.counter = timer.reload;
+                overflowed.push(index);
+                self.cascade(index + 1, &mut overflowed);
+            }
+        }
+
+        overflowed
+    }
+
+    fn cascade(&mut self, index: usize, overflowed: &mut Vec<usize>) {
+        if index == 4 {
+            return;
+        }
+
+        let timer = &mut self.timers[index];
+        if !timer.enabled() || (timer.control & tmcnt::CASCADE) == 0 {
+            return;
+        }
+
+        timer.counter = timer.counter.wrapping_add(1);
+        if timer.counter == 0 {
+            timer.counter = timer.reload;
+            overflowed.push(index);
+            self.cascade(index + 1, overflowed);
+        }
+    }
+
+    pub fn irq_enabled(&self, index: usize) -> bool {
+        (self.timers[index].control & tmcnt::IRQ) != 0
+    }
+
+    // TMxCNT_L reads the count, TMxCNT_H the settings
+    pub fn read(&self, addr: usize, now: u64) -> u8 {
+        let offset = addr - io_address::TM0CNT_L;
+        let index = offset / TIMER_STRIDE;
+        let timer = &self.timers[index];
+
+        match offset % TIMER_STRIDE {
+            0 => timer.counter(now, self.clocked(index)) as u8,
+            1 => (timer.counter(now, self.clocked(index)) >> 8) as u8,
+            2 => timer.control & tmcnt::READ_MASK,
+            _ => 0,
+        }
+    }
+
+    // TMxCNT_L sets the reload value, which is only loaded on overflow and
+    // when the timer starts
+    pub fn write(&mut self, addr: usize, data: u8, now: u64) {
+        let offset = addr - io_address::TM0CNT_L;
+        let index = offset / TIMER_STRIDE;
+        let clocked = self.clocked(index);
+        let timer = &mut self.timers[index];
+
+        match offset % TIMER_STRIDE {
+            0 => timer.reload = (timer.reload & 0xFF00) | data as u16,
+            1 => timer.reload = (timer.reload & 0x00FF) | ((data as u16) << 8),
+            2 => {
+                // bring the count up to date before the clock it runs from
+                // changes, keeping any progress towards the next tick so a
+                // write that leaves the timer running doesn't hold it back
+                let progress = if clocked { (now - timer.since) & ((1 << timer.shift()) - 1) } else { 0 };
+                timer.counter = timer.counter(now, clocked);
+
+                let starting = !timer.enabled() && (data & tmcnt::ENABLE) != 0;
+                timer.control = data;
+                timer.since = now - (progress & ((1 << timer.shift()) - 1));
+                if starting {
+                    timer.counter = timer.reload;
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TM0CNT_H: usize = io_address::TM0CNT_L + 2;
+
+    fn count(timers: &Timers, now: u64) -> u16 {
+        timers.read(io_address::TM0CNT_L, now) as u16 | ((timers.read(io_address::TM0CNT_L + 1, now) as u16) << 8)
+    }
+
+    #[test]
+    fn control_writes_keep_prescaler_progress() {
+        let mut timers = Timers::new();
+        timers.write(TM0CNT_H, tmcnt::ENABLE | 3, 0);
+
+        timers.write(TM0CNT_H, tmcnt::ENABLE | tmcnt::IRQ | 3, 1000);
+        assert_eq!(count(&timers, 1023), 0);
+        assert_eq!(count(&timers, 1024), 1);
+        assert_eq!(timers.cycles_to_overflow(1000), (0x1_0000 << 10) - 1000);
+    }
+
+    #[test]
+    fn starting_counts_from_the_reload_value() {
+        let mut timers = Timers::new();
+        timers.write(io_address::TM0CNT_L, 0xF0, 0);
+        timers.write(io_address::TM0CNT_L + 1, 0xFF, 0);
+        timers.write(TM0CNT_H, tmcnt::ENABLE | 1, 100);
+
+        assert_eq!(count(&timers, 100 + 63), 0xFFF0);
+        assert_eq!(count(&timers, 100 + 64), 0xFFF1);
+        assert_eq!(timers.overflows(100 + (16 << 6)), [0]);
+        assert_eq!(count(&timers, 100 + (16 << 6)), 0xFFF0);
+    }
+}
@@ -0,0 +1,4 @@
+// turning the console's picture into something for a modern screen. none
+// of this is console hardware, it all happens after the ppu is done
+pub mod color;
+pub mod scale;
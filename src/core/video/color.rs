@@ -0,0 +1,166 @@
+use crate::core::ppu::rgb888;
+
+// the screen the colours should look like they're on
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorCorrection {
+    None,
+    // the original unlit screen, dark and washed out
+    Gba,
+    // the frontlit sp, brighter but still muted
+    GbaSp,
+    // the backlit micro, close to the raw colours
+    Micro,
+}
+
+// how a screen mixes the channels and how it responds to level. games were
+// coloured to look right through these, so reproducing them undoes the
+// oversaturation of showing the raw values on a modern display
+struct Profile {
+    // rows give each output channel as a mix of red, green and blue, and
+    // each sums to 1 so white stays white
+    matrix: [[f32; 3]; 3],
+    luminance: f32,
+    // the response of the console's screen, and of the one we draw to
+    screen_gamma: f32,
+    display_gamma: f32,
+}
+
+const GBA: Profile = Profile {
+    matrix: [[0.82, 0.24, -0.06], [0.125, 0.665, 0.21], [0.195, 0.075, 0.73]],
+    luminance: 0.94,
+    screen_gamma: 2.7,
+    display_gamma: 2.2,
+};
+
+const GBA_SP: Profile = Profile {
+    matrix: [[0.86, 0.10, 0.04], [0.03, 0.855, 0.115], [0.0325, 0.0375, 0.93]],
+    luminance: 0.97,
+    screen_gamma: 2.4,
+    display_gamma: 2.2,
+};
+
+const MICRO: Profile = Profile {
+    matrix: [[0.94, 0.04, 0.02], [0.03, 0.94, 0.03], [0.02, 0.04, 0.94]],
+    luminance: 1.0,
+    screen_gamma: 2.2,
+    display_gamma: 2.2,
+};
+
+impl ColorCorrection {
+    pub fn parse(name: &str) -> Option<ColorCorrection> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(ColorCorrection::None),
+            "gba" => Some(ColorCorrection::Gba),
+            "sp" | "gbasp" => Some(ColorCorrection::GbaSp),
+            "micro" => Some(ColorCorrection::Micro),
+            _ => None,
+        }
+    }
+
+    fn profile(&self) -> Option<&'static Profile> {
+        match self {
+            ColorCorrection::None => None,
+            ColorCorrection::Gba => Some(&GBA),
+            ColorCorrection::GbaSp => Some(&GBA_SP),
+            ColorCorrection::Micro => Some(&MICRO),
+        }
+    }
+}
+
+// turns finished frames into 8 bit rgb for showing, correcting the colours
+// and optionally blending each frame with the ones before it. the lcd was
+// slow to change, so games could flicker sprites on alternate frames and
+// have them come out see-through
+// any more and a moving sprite would leave a trail for most of a second
+pub const MAX_PERSISTENCE: f32 = 0.95;
+
+pub struct ColorFilter {
+    correction: ColorCorrection,
+    // how much of the previous output stays on screen, 0 for none
+    persistence: f32,
+    // the 32 screen levels in linear light
+    linear: [f32; 32],
+    previous: Vec<[f32; 3]>,
+}
+
+impl ColorFilter {
+    pub fn new(correction: ColorCorrection, persistence: f32) -> ColorFilter {
+        let gamma = correction.profile().map_or(1.0, |profile| profile.screen_gamma);
+        let mut linear = [0.0; 32];
+        for (level, value) in linear.iter_mut().enumerate() {
+            *value = (level as f32 / 31.0).powf(gamma);
+        }
+
+        ColorFilter {
+            correction,
+            persistence: persistence.clamp(0.0, MAX_PERSISTENCE),
+            linear,
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, frame: &[u16]) -> Vec<u8> {
+        let profile = match self.correction.profile() {
+            Some(profile) => profile,
+            None if self.persistence == 0.0 => return frame.iter().flat_map(|&color| rgb888(color)).collect(),
+            None => &IDENTITY,
+        };
+
+        if self.previous.len() != frame.len() {
+            self.previous = vec![[0.0; 3]; frame.len()];
+            // nothing to blend with on the first frame
+            for (previous, &color) in self.previous.iter_mut().zip(frame) {
+                *previous = mix(&self.linear, profile, color);
+            }
+        }
+
+        let mut output = Vec::with_capacity(frame.len() * 3);
+        for (previous, &color) in self.previous.iter_mut().zip(frame) {
+            let current = mix(&self.linear, profile, color);
+
+            for channel in 0..3 {
+                let value = current[channel] * (1.0 - self.persistence) + previous[channel] * self.persistence;
+                previous[channel] = value;
+                output.push((value.clamp(0.0, 1.0).powf(1.0 / profile.display_gamma) * 255.0).round() as u8);
+            }
+        }
+
+        output
+    }
+}
+
+// the colour in linear light as the modelled screen shows it
+fn mix(linear: &[f32; 32], profile: &Profile, color: u16) -> [f32; 3] {
+    let input = [
+        linear[(color & 0x1F) as usize],
+        linear[((color >> 5) & 0x1F) as usize],
+        linear[((color >> 10) & 0x1F) as usize],
+    ];
+
+    profile.matrix.map(|row| (row[0] * input[0] + row[1] * input[1] + row[2] * input[2]) * profile.luminance)
+}
+
+// frame blending without any colour change. the levels are kept as they
+// are, so there's no gamma to undo either
+const IDENTITY: Profile = Profile {
+    matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    luminance: 1.0,
+    screen_gamma: 1.0,
+    display_gamma: 1.0,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_stays_white_under_every_profile() {
+        for correction in [ColorCorrection::Gba, ColorCorrection::GbaSp, ColorCorrection::Micro] {
+            let profile = correction.profile().unwrap();
+            let level = (profile.luminance.powf(1.0 / profile.display_gamma) * 255.0).round() as u8;
+
+            let output = ColorFilter::new(correction, 0.0).apply(&[0x7FFF]);
+            assert_eq!(output, [level; 3]);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// a picture as 8 bit rgb, row by row
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+type Pixel = [u8; 3];
+
+// ways of blowing the picture up for bigger screens and captures. the pixel
+// art filters look at each pixel's neighbours to guess at the edges the
+// artist meant and smooth them, the others keep the pixels square
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scaler {
+    // each pixel repeated into an n by n block, 1 leaves the picture alone
+    Nearest(usize),
+    Scale2x,
+    Scale3x,
+    Hq2x,
+    Xbr2x,
+    // n by n blocks with darker lines between them, like the gaps between
+    // the cells of the lcd
+    LcdGrid(usize),
+}
+
+const MAX_FACTOR: usize = 8;
+const DEFAULT_GRID: usize = 3;
+
+// how much of the colour the lines between lcd cells let through, out of 256
+const GRID_LEVEL: u16 = 160;
+
+impl Scaler {
+    // none, 2x to 8x, nearest<n>, scale2x, scale3x, hq2x, xbr, lcd or lcd<n>
+    pub fn parse(name: &str) -> Option<Scaler> {
+        let name = name.to_ascii_lowercase();
+        let factor = |digits: &str| match digits.parse() {
+            Ok(factor) if (1..=MAX_FACTOR).contains(&factor) => Some(factor),
+            _ => None,
+        };
+
+        match name.as_str() {
+            "none" => Some(Scaler::Nearest(1)),
+            "scale2x" => Some(Scaler::Scale2x),
+            "scale3x" => Some(Scaler::Scale3x),
+            "hq2x" => Some(Scaler::Hq2x),
+            "xbr" | "xbr2x" => Some(Scaler::Xbr2x),
+            "lcd" => Some(Scaler::LcdGrid(DEFAULT_GRID)),
+            _ => {
+                if let Some(digits) = name.strip_prefix("lcd") {
+                    factor(digits).filter(|&factor| factor > 1).map(Scaler::LcdGrid)
+                } else if let Some(digits) = name.strip_prefix("nearest") {
+                    factor(digits).map(Scaler::Nearest)
+                } else {
+                    factor(name.strip_suffix('x')?).map(Scaler::Nearest)
+                }
+            }
+        }
+    }
+
+    // how many times bigger each side of the output is
+    pub fn factor(&self) -> usize {
+        match self {
+            Scaler::Nearest(factor) | Scaler::LcdGrid(factor) => *factor,
+            Scaler::Scale2x | Scaler::Hq2x | Scaler::Xbr2x => 2,
+            Scaler::Scale3x => 3,
+        }
+    }
+
+    pub fn scale(&self, frame: &Frame) -> Frame {
+        let source = Source {
+            width: frame.width,
+            height: frame.height,
+            pixels: frame.rgb.chunks(3).map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect(),
+        };
+
+        let factor = self.factor();
+        let mut output = Output::new(frame.width * factor, frame.height * factor);
+
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let block = |dx: usize, dy: usize| (x * factor + dx, y * factor + dy);
+
+                match self {
+                    Scaler::Nearest(_) => {
+                        let pixel = source.at(x, y, 0, 0);
+                        for dy in 0..factor {
+                            for dx in 0..factor {
+                                output.set(block(dx, dy), pixel);
+                            }
+                        }
+                    },
+                    Scaler::LcdGrid(_) => {
+                        let pixel = source.at(x, y, 0, 0);
+                        for dy in 0..factor {
+                            for dx in 0..factor {
+                                let gap = dx == factor - 1 || dy == factor - 1;
+                                output.set(block(dx, dy), if gap { darken(pixel) } else { pixel });
+                            }
+                        }
+                    },
+                    Scaler::Scale3x => {
+                        for (i, pixel) in scale3x(&source, x, y).into_iter().enumerate() {
+                            output.set(block(i % 3, i / 3), pixel);
+                        }
+                    },
+                    _ => {
+                        // the 2x filters work out each quarter the same way,
+                        // looking at the picture mirrored so the corner being
+                        // filled is always the bottom right one
+                        for (sx, sy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                            let near = |dx: isize, dy: isize| source.at(x, y, dx * sx, dy * sy);
+                            let pixel = match self {
+                                Scaler::Scale2x => scale2x_corner(near),
+                                Scaler::Hq2x => hq2x_corner(near),
+                                _ => xbr_corner(near),
+                            };
+                            output.set(block((sx + 1) as usize / 2, (sy + 1) as usize / 2), pixel);
+                        }
+                    },
+                }
+            }
+        }
+
+        Frame { width: output.width, height: output.height, rgb: output.rgb }
+    }
+}
+
+struct Source {
+    width: usize,
+    height: usize,
+    pixels: Vec<Pixel>,
+}
+
+impl Source {
+    // a neighbour of (x, y), repeating the edge pixels past the sides
+    fn at(&self, x: usize, y: usize, dx: isize, dy: isize) -> Pixel {
+        let x = (x as isize + dx).clamp(0, self.width as isize - 1) as usize;
+        let y = (y as isize + dy).clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+}
+
+struct Output {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Output {
+    fn new(width: usize, height: usize) -> Output {
+        Output { width, height, rgb: vec![0; width * height * 3] }
+    }
+
+    fn set(&mut self, (x, y): (usize, usize), pixel: Pixel) {
+        let i = (y * self.width + x) * 3;
+        self.rgb[i..i + 3].copy_from_slice(&pixel);
+    }
+}
+
+// the corner of the bottom right quarter, as in the original epx. it takes
+// the colour of the two neighbours it touches when they agree and the other
+// two don't
+fn scale2x_corner(near: impl Fn(isize, isize) -> Pixel) -> Pixel {
+    let (right, down) = (near(1, 0), near(0, 1));
+    let (left, up) = (near(-1, 0), near(0, -1));
+
+    if right == down && right != up && down != left { right } else { near(0, 0) }
+}
+
+// the nine pixels of a 3x3 block, row by row, following advmame's scale3x
+fn scale3x(source: &Source, x: usize, y: usize) -> [Pixel; 9] {
+    let near = |dx: isize, dy: isize| source.at(x, y, dx, dy);
+    let [a, b, c] = [near(-1, -1), near(0, -1), near(1, -1)];
+    let [d, e, f] = [near(-1, 0), near(0, 0), near(1, 0)];
+    let [g, h, i] = [near(-1, 1), near(0, 1), near(1, 1)];
+
+    let pick = |condition: bool, color: Pixel| if condition { color } else { e };
+    [
+        pick(d == b && b != f && d != h, d),
+        pick((d == b && b != f && d != h && e != c) || (b == f && b != d && f != h && e != a), b),
+        pick(b == f && b != d && f != h, f),
+        pick((d == b && b != f && d != h && e != g) || (d == h && d != b && h != f && e != a), d),
+        e,
+        pick((b == f && b != d && f != h && e != i) || (h == f && d != h && b != f && e != c), f),
+        pick(d == h && d != b && h != f, d),
+        pick((d == h && d != b && h != f && e != i) || (h == f && d != h && b != f && e != g), h),
+        pick(h == f && d != h && b != f, f),
+    ]
+}
+
+// in the spirit of hq2x rather than its full lookup table. neighbours are
+// compared by brightness and hue with a tolerance so gradients count as the
+// same colour, and the corner is blended rather than copied
+fn hq2x_corner(near: impl Fn(isize, isize) -> Pixel) -> Pixel {
+    let center = near(0, 0);
+    let (right, down, diagonal) = (near(1, 0), near(0, 1), near(1, 1));
+
+    let edge = similar(right, down) && !similar(center, right) && !similar(center, diagonal);
+    if edge {
+        // a diagonal edge crosses the corner
+        blend(&[(center, 2), (right, 1), (down, 1)])
+    } else if similar(center, right) || similar(center, down) {
+        center
+    } else if !similar(center, diagonal) {
+        // an isolated pixel, softened a little towards its surroundings
+        blend(&[(center, 6), (right, 1), (down, 1)])
+    } else {
+        center
+    }
+}
+
+// the first level of hyllian's xbr. the edge through the corner is the
+// direction where the colours change least along it, found by weighing the
+// differences either side. the corner goes halfway to the neighbour it runs
+// into when that edge doesn't follow the pixel's own diagonal
+fn xbr_corner(near: impl Fn(isize, isize) -> Pixel) -> Pixel {
+    let e = near(0, 0);
+    let (b, c, d, f) = (near(0, -1), near(1, -1), near(-1, 0), near(1, 0));
+    let (g, h, i) = (near(-1, 1), near(0, 1), near(1, 1));
+    let (f4, h5, i4, i5) = (near(2, 0), near(0, 2), near(2, 1), near(1, 2));
+
+    let across = distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4 * distance(h, f);
+    let along = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, i);
+
+    if across < along {
+        let towards = if distance(e, f) <= distance(e, h) { f } else { h };
+        blend(&[(e, 1), (towards, 1)])
+    } else {
+        e
+    }
+}
+
+fn yuv(pixel: Pixel) -> [i32; 3] {
+    let [r, g, b] = pixel.map(|channel| channel as i32);
+    [(r * 299 + g * 587 + b * 114) / 1000, (b - r) / 4 + 128, (2 * r - g - b) / 4 + 128]
+}
+
+// weights brightness over hue, as the eye does
+fn distance(a: Pixel, b: Pixel) -> u32 {
+    let (a, b) = (yuv(a), yuv(b));
+    48 * a[0].abs_diff(b[0]) + 7 * a[1].abs_diff(b[1]) + 6 * a[2].abs_diff(b[2])
+}
+
+// the thresholds hq2x uses for treating two colours as the same
+fn similar(a: Pixel, b: Pixel) -> bool {
+    let (a, b) = (yuv(a), yuv(b));
+    a[0].abs_diff(b[0]) <= 48 && a[1].abs_diff(b[1]) <= 7 && a[2].abs_diff(b[2]) <= 6
+}
+
+fn blend(weighted: &[(Pixel, u32)]) -> Pixel {
+    let total: u32 = weighted.iter().map(|(_, weight)| weight).sum();
+    let channel = |i: usize| (weighted.iter().map(|(pixel, weight)| pixel[i] as u32 * weight).sum::<u32>() / total) as u8;
+    [channel(0), channel(1), channel(2)]
+}
+
+fn darken(pixel: Pixel) -> Pixel {
+    pixel.map(|channel| ((channel as u16 * GRID_LEVEL) >> 8) as u8)
+}
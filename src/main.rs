@@ -1,9 +1,30 @@
 mod core;
+mod checksum;
 mod constants;
+mod png;
+mod wav;
+
+use std::env;
+use std::path::Path;
+use std::process;
 
 use crate::core::bus::Memory;
 use crate::core::bus::BusAccess;
+use crate::core::bios::Bios;
+use crate::core::cartridge::Cartridge;
+use crate::core::cartridge::Header;
+use crate::core::cartridge::backup::BackupType;
+use crate::core::cartridge::gpio::rtc::Clock;
+use crate::core::cartridge::save_file::SaveFile;
 use crate::core::cpu::ARM7TDMI;
+use crate::core::elf::Elf;
+use crate::core::gba::GameBoyAdvance;
+use crate::core::multiboot::Multiboot;
+use crate::core::screenshot::ImageFormat;
+use crate::core::video::color::ColorCorrection;
+use crate::core::video::color::ColorFilter;
+use crate::core::video::color::MAX_PERSISTENCE;
+use crate::core::video::scale::Scaler;
 
 /* TEST 1 - BASIC MEMORY OPERATIONS
 fn main() {
@@ -45,6 +66,362 @@ fn main() {
 }
 */
 
+struct Options {
+    rom_path: String,
+    bios_path: Option<String>,
+    patch_path: Option<String>,
+    save_type: Option<BackupType>,
+    save_dir: Option<String>,
+    clock: Clock,
+    multiboot: bool,
+    frames: u32,
+    screenshot: Option<(String, ImageFormat)>,
+    dump_vram: Option<String>,
+    tile_palette: usize,
+    color_correction: ColorCorrection,
+    frame_blend: f32,
+    scaler: Scaler,
+    record: Option<String>,
+    record_channels: bool,
+    disassemble: usize,
+}
+
+const USAGE: &str = "[--bios <bios.bin>] [--patch <patch>] [--save-type <type>] [--save-dir <dir>] \
+[--rtc-fixed <unix time> | --rtc-offset <seconds>] [--multiboot] [--frames <count>] \
+[--screenshot <file>] [--screenshot-format <png | rgb888 | rgb555>] [--dump-vram <dir>] [--tile-palette <bank>] \
+[--color-correction <none | gba | sp | micro>] [--frame-blend <amount>] \
+[--scale <none | <n>x | scale2x | scale3x | hq2x | xbr | lcd<n>>] \
+[--record <file.wav> [--record-channels]] [--disassemble <count>] <rom.gba | program.mb | program.elf>";
+
+fn parse_args(args: &[String]) -> Options {
+    let mut rom_path: Option<String> = None;
+    let mut options = Options {
+        rom_path: String::new(),
+        bios_path: None,
+        patch_path: None,
+        save_type: None,
+        save_dir: None,
+        clock: Clock::Host,
+        multiboot: false,
+        frames: 0,
+        screenshot: None,
+        dump_vram: None,
+        tile_palette: 0,
+        color_correction: ColorCorrection::None,
+        frame_blend: 0.0,
+        scaler: Scaler::Nearest(1),
+        record: None,
+        record_channels: false,
+        disassemble: 0,
+    };
+    let mut screenshot_format: Option<ImageFormat> = None;
+    let mut screenshot_path: Option<String> = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--bios" if i + 1 < args.len() => {
+                options.bios_path = Some(args[i + 1].clone());
+                i += 1;
+            },
+            "--save-type" if i + 1 < args.len() => {
+                options.save_type = match BackupType::parse(&args[i + 1]) {
+                    Some(backup_type) => Some(backup_type),
+                    None => {
+                        eprintln!("unknown save type {}, expected none, sram, flash64k, flash64k-panasonic, \
+                            flash64k-atmel, flash128k, flash128k-sanyo, eeprom, eeprom512 or eeprom8k", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            "--patch" if i + 1 < args.len() => {
+                options.patch_path = Some(args[i + 1].clone());
+                i += 1;
+            },
+            "--save-dir" if i + 1 < args.len() => {
+                options.save_dir = Some(args[i + 1].clone());
+                i += 1;
+            },
+            "--rtc-fixed" | "--rtc-offset" if i + 1 < args.len() => {
+                let seconds: i64 = match args[i + 1].parse() {
+                    Ok(seconds) => seconds,
+                    Err(_) => {
+                        eprintln!("{} expects a number of seconds, got {}", args[i], args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                options.clock = if args[i] == "--rtc-fixed" { Clock::Fixed(seconds) } else { Clock::Offset(seconds) };
+                i += 1;
+            },
+            "--multiboot" => options.multiboot = true,
+            "--disassemble" if i + 1 < args.len() => {
+                options.disassemble = match args[i + 1].parse() {
+                    Ok(count) => count,
+                    Err(_) => {
+                        eprintln!("--disassemble expects a number of instructions, got {}", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            "--frames" if i + 1 < args.len() => {
+                options.frames = match args[i + 1].parse() {
+                    Ok(frames) => frames,
+                    Err(_) => {
+                        eprintln!("--frames expects a number of frames, got {}", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            "--screenshot" if i + 1 < args.len() => {
+                screenshot_path = Some(args[i + 1].clone());
+                i += 1;
+            },
+            "--dump-vram" if i + 1 < args.len() => {
+                options.dump_vram = Some(args[i + 1].clone());
+                i += 1;
+            },
+            "--tile-palette" if i + 1 < args.len() => {
+                options.tile_palette = match args[i + 1].parse() {
+                    Ok(bank) if bank < 16 => bank,
+                    _ => {
+                        eprintln!("--tile-palette expects a palette bank from 0 to 15, got {}", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            "--color-correction" if i + 1 < args.len() => {
+                options.color_correction = match ColorCorrection::parse(&args[i + 1]) {
+                    Some(correction) => correction,
+                    None => {
+                        eprintln!("unknown colour correction {}, expected none, gba, sp or micro", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            "--frame-blend" if i + 1 < args.len() => {
+                options.frame_blend = match args[i + 1].parse() {
+                    Ok(amount) if (0.0..=MAX_PERSISTENCE).contains(&amount) => amount,
+                    _ => {
+                        eprintln!("--frame-blend expects how much of the last frame to keep, from 0 to {}, got {}", MAX_PERSISTENCE, args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            "--scale" if i + 1 < args.len() => {
+                options.scaler = match Scaler::parse(&args[i + 1]) {
+                    Some(scaler) => scaler,
+                    None => {
+                        eprintln!("unknown scaler {}, expected none, 2x to 8x, scale2x, scale3x, hq2x, xbr or lcd<n>", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            "--record" if i + 1 < args.len() => {
+                options.record = Some(args[i + 1].clone());
+                i += 1;
+            },
+            "--record-channels" => options.record_channels = true,
+            "--screenshot-format" if i + 1 < args.len() => {
+                screenshot_format = match ImageFormat::parse(&args[i + 1]) {
+                    Some(format) => Some(format),
+                    None => {
+                        eprintln!("unknown image format {}, expected png, rgb888 or rgb555", args[i + 1]);
+                        process::exit(1);
+                    }
+                };
+                i += 1;
+            },
+            arg => rom_path = Some(arg.to_string()),
+        }
+        i += 1;
+    }
+
+    options.rom_path = match rom_path {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: {} {}", args[0], USAGE);
+            process::exit(1);
+        }
+    };
+
+    if let Some(path) = screenshot_path {
+        let format = match screenshot_format.or_else(|| ImageFormat::from_path(Path::new(&path))) {
+            Some(format) => format,
+            None => {
+                eprintln!("can't tell the image format of {}, use --screenshot-format", path);
+                process::exit(1);
+            }
+        };
+        options.screenshot = Some((path, format));
+    }
+
+    // multiboot programs are conventionally given a .mb extension
+    if options.rom_path.ends_with(".mb") {
+        options.multiboot = true;
+    }
+
+    options
+}
+
+fn print_header(header: &Header) {
+    println!("title: {}", header.title);
+    println!("game code: {}", header.game_code);
+    println!("maker code: {}", header.maker_code);
+    println!("unit code: {}", header.unit_code);
+    println!("version: {}", header.software_version);
+    println!("entry offset: {:#x}", header.entry_offset);
+}
+
+fn insert_cartridge(gba: &mut GameBoyAdvance, options: &Options) {
+    let rom_path = &options.rom_path;
+    let cartridge = match Cartridge::load(Path::new(rom_path), options.patch_path.as_deref().map(Path::new)) {
+        Ok(cartridge) => cartridge,
+        Err(err) => {
+            eprintln!("{}: {}", rom_path, err);
+            process::exit(1);
+        }
+    };
+
+    if let Some(patch) = &cartridge.patch {
+        println!("patch: {}", patch.display());
+    }
+    print_header(&cartridge.header);
+
+    set_up_cartridge(gba, options, cartridge);
+}
+
+// save hardware, the clock and the save file, for any program with a rom
+fn set_up_cartridge(gba: &mut GameBoyAdvance, options: &Options, mut cartridge: Cartridge) {
+    let rom_path = &options.rom_path;
+
+    let (backup_type, source) = cartridge.detect_backup(options.save_type);
+    println!("save type: {} ({})", backup_type, source);
+
+    if cartridge.detect_rtc(options.clock) {
+        println!("rtc: present");
+    }
+
+    gba.insert_cartridge(cartridge);
+
+    let save_file = SaveFile::new(Path::new(rom_path), options.save_dir.as_deref().map(Path::new));
+    let save_path = save_file.path.display().to_string();
+    if let Err(err) = gba.attach_save_file(save_file) {
+        eprintln!("{}: could not load save: {}", save_path, err);
+    }
+}
+
+fn load_multiboot(gba: &mut GameBoyAdvance, options: &Options) {
+    let multiboot = match Multiboot::load(Path::new(&options.rom_path)) {
+        Ok(multiboot) => multiboot,
+        Err(err) => {
+            eprintln!("{}: {}", options.rom_path, err);
+            process::exit(1);
+        }
+    };
+
+    print_header(&multiboot.header);
+    println!("multiboot: {} bytes", multiboot.image.len());
+
+    gba.load_multiboot(multiboot);
+}
+
+// cartridge elfs bring their own rom, multiboot ones run from ewram alone
+fn load_elf(gba: &mut GameBoyAdvance, options: &Options) {
+    let elf = match Elf::load(Path::new(&options.rom_path)) {
+        Ok(elf) => elf,
+        Err(err) => {
+            eprintln!("{}: {}", options.rom_path, err);
+            process::exit(1);
+        }
+    };
+
+    println!("elf: {} segments, {} symbols", elf.segments.len(), elf.symbols.len());
+
+    if let Some(image) = elf.rom_image() {
+        let cartridge = Cartridge::from_image(image);
+        print_header(&cartridge.header);
+        set_up_cartridge(gba, options, cartridge);
+    }
+
+    gba.load_elf(elf);
+    println!("entry: {}", gba.symbols.label(gba.entry_point));
+}
+
 fn main() {
-    println!("Test");
+    let args: Vec<String> = env::args().collect();
+    let options = parse_args(&args);
+    let mut gba = GameBoyAdvance::new();
+
+    if options.rom_path.ends_with(".elf") {
+        load_elf(&mut gba, &options);
+    } else if options.multiboot {
+        load_multiboot(&mut gba, &options);
+    } else {
+        insert_cartridge(&mut gba, &options);
+    }
+
+    match &options.bios_path {
+        Some(path) => match Bios::load(Path::new(path)) {
+            Ok(bios) => gba.boot_bios(bios),
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                process::exit(1);
+            }
+        },
+        None => gba.boot_direct(),
+    }
+
+    for line in gba.disassemble(options.disassemble) {
+        println!("{}", line);
+    }
+
+    gba.color_filter = ColorFilter::new(options.color_correction, options.frame_blend);
+    gba.scaler = options.scaler;
+
+    if let Some(path) = &options.record {
+        if let Err(err) = gba.start_recording(Path::new(path), options.record_channels) {
+            eprintln!("{}: could not start recording: {}", path, err);
+            process::exit(1);
+        }
+    }
+
+    // runs without a window, for scripts and tests
+    for _ in 0..options.frames {
+        gba.run_frame();
+        if let Err(err) = gba.update_save() {
+            eprintln!("could not write save: {}", err);
+        }
+        if let Err(err) = gba.update_recording() {
+            eprintln!("could not write recording: {}", err);
+        }
+    }
+
+    if let Err(err) = gba.stop_recording() {
+        eprintln!("could not finish recording: {}", err);
+    }
+
+    if let Some((path, format)) = &options.screenshot {
+        if let Err(err) = gba.save_screenshot(Path::new(path), *format) {
+            eprintln!("{}: could not write screenshot: {}", path, err);
+            process::exit(1);
+        }
+    }
+
+    if let Some(dir) = &options.dump_vram {
+        if let Err(err) = gba.dump_vram(Path::new(dir), options.tile_palette) {
+            eprintln!("{}: could not dump vram: {}", dir, err);
+            process::exit(1);
+        }
+    }
+
+    if let Err(err) = gba.flush_save() {
+        eprintln!("could not write save: {}", err);
+    }
 }
@@ -0,0 +1,63 @@
+use crate::checksum::adler32;
+use crate::checksum::crc32_update;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const COLOR_TYPE_RGB: u8 = 2;
+const FILTER_NONE: u8 = 0;
+// the most a stored deflate block can hold
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+// writes 8 bit rgb pixels out as a png. the image data goes in stored
+// (uncompressed) deflate blocks, which every decoder reads and keeps this
+// free of a compressor
+pub fn encode(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, COLOR_TYPE_RGB, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // every row starts with the filter it was encoded with
+    let mut rows = Vec::with_capacity(height * (width * 3 + 1));
+    for row in rgb.chunks(width * 3).take(height) {
+        rows.push(FILTER_NONE);
+        rows.extend_from_slice(row);
+    }
+
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&rows));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let crc = crc32_update(crc32_update(0, kind), data);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32 KiB window and no preset dictionary, check bits
+    // chosen so the header is a multiple of 31
+    let mut stream = vec![0x78, 0x01];
+
+    let blocks = data.chunks(STORED_BLOCK_SIZE).count().max(1);
+    for i in 0..blocks {
+        let start = i * STORED_BLOCK_SIZE;
+        let block = &data[start..(start + STORED_BLOCK_SIZE).min(data.len())];
+        let length = block.len() as u16;
+
+        stream.push((i == blocks - 1) as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}